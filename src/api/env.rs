@@ -1,8 +1,21 @@
-
 pub fn running_on_fly() -> bool {
     current_app_name().is_some()
 }
 
 pub fn current_app_name() -> Option<String> {
-    std::env::var("FLY_APP_NAME").ok()
-}
\ No newline at end of file
+    non_empty_var("FLY_APP_NAME")
+}
+
+/// The token set by `fly` in machines and deploys, or by the user.
+pub fn api_token() -> Option<String> {
+    non_empty_var("FLY_API_TOKEN")
+}
+
+/// Overrides the Machines API endpoint.
+pub fn flaps_base_url() -> Option<String> {
+    non_empty_var("FLY_FLAPS_BASE_URL")
+}
+
+fn non_empty_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
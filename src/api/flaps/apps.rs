@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use super::{
+    Client, RawClient, FlapsSettings, Transport, FlapsClientCreationError, Result, ApiEndpoint, AsAppName,
+    UrlParam, encode_url_params, ignore_body, CreateAppRequest, ListAppsResponse,
+};
+use crate::entities::app::App;
+
+/// Client for managing the apps of an organization, under `/v1/apps`.
+///
+/// Cloning is cheap, and clones share the same transport.
+/// Use [`app_client`](Self::app_client) to manage the machines of an app.
+#[derive(Clone)]
+pub struct OrgClient {
+    pub(super) raw: Arc<RawClient>,
+    pub(super) org_slug: String,
+}
+
+impl OrgClient {
+
+    pub fn new(cfg: FlapsSettings, org_slug: String) -> std::result::Result<OrgClient, FlapsClientCreationError> {
+        Self::from_raw(Arc::new(RawClient::new_http(cfg)?), org_slug)
+    }
+
+    /// Creates a client that sends its requests through a custom [`Transport`].
+    /// The settings' auth token is unused; the transport is responsible for authentication.
+    pub fn with_transport(cfg: FlapsSettings, org_slug: String, transport: Arc<dyn Transport>) -> std::result::Result<OrgClient, FlapsClientCreationError> {
+        Self::from_raw(Arc::new(RawClient::with_transport(cfg, transport)?), org_slug)
+    }
+
+    fn from_raw(raw: Arc<RawClient>, org_slug: String) -> std::result::Result<OrgClient, FlapsClientCreationError> {
+        if org_slug.is_empty() || !org_slug.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-'|'_')) {
+            return Err(FlapsClientCreationError::InvalidOrgSlug(org_slug));
+        }
+        Ok(OrgClient { raw, org_slug })
+    }
+
+    pub fn org_slug(&self) -> &str {
+        &self.org_slug
+    }
+
+    /// Creates a machines client for `app`, sharing this client's transport and credentials.
+    pub fn app_client<A: AsAppName>(&self, app: &A) -> std::result::Result<Client, FlapsClientCreationError> {
+        Client::from_raw(self.raw.clone(), app.as_app_name().to_string())
+    }
+
+    fn apps_url(&self, endpoint: &str) -> Result<url::Url> {
+        let sep = if endpoint.is_empty() || endpoint.starts_with('?') { "" } else { "/" };
+        Ok(self.raw.base_url.join(&format!("v1/apps{sep}{endpoint}"))?)
+    }
+
+    /// Creates an app in this organization, then fetches it.
+    /// `network` selects a custom private network; `None` uses the organization's default.
+    pub async fn create_app(&self, name: String, network: Option<String>) -> Result<App> {
+        let req = CreateAppRequest {
+            name,
+            org_slug: self.org_slug.clone(),
+            network,
+        };
+        self.raw.make_request(reqwest::Method::POST, self.apps_url("")?, &req, Vec::new(), ApiEndpoint::Other).await.map(ignore_body)?;
+
+        self.get_app(&req.name).await
+    }
+
+    pub async fn get_app<A: AsAppName>(&self, app: &A) -> Result<App> {
+        let app_name = urlencoding::encode(app.as_app_name());
+
+        self.raw.make_request(reqwest::Method::GET, self.apps_url(&app_name)?, (), Vec::new(), ApiEndpoint::Other).await
+    }
+
+    pub async fn list_apps(&self) -> Result<Vec<App>> {
+        let query = encode_url_params(&[UrlParam("org_slug", &self.org_slug)]);
+
+        let res: ListAppsResponse = self.raw.make_request(reqwest::Method::GET, self.apps_url(&query)?, (), Vec::new(), ApiEndpoint::Other).await?;
+        Ok(res.apps)
+    }
+
+    /// Deletes an app, destroying all of its machines and volumes.
+    pub async fn delete_app<A: AsAppName>(&self, app: &A) -> Result<()> {
+        let app_name = urlencoding::encode(app.as_app_name());
+
+        self.raw.make_request(reqwest::Method::DELETE, self.apps_url(&app_name)?, (), Vec::new(), ApiEndpoint::Other).await.map(ignore_body)
+    }
+}
@@ -0,0 +1,52 @@
+//! Minimal GraphQL support, for the few operations that the Machines API doesn't expose.
+
+use super::{RawClient, Result, FlapsError, RawApiError, ApiEndpoint};
+
+#[derive(serde::Serialize)]
+struct GraphQlRequest<'a, V> {
+    query: &'a str,
+    variables: V,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    errors: Option<Vec<GraphQlError>>,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlError {
+    message: String,
+    extensions: Option<GraphQlErrorExtensions>,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlErrorExtensions {
+    code: Option<String>,
+}
+
+impl RawClient {
+    pub(super) async fn graphql<
+        Res: serde::de::DeserializeOwned,
+        V: serde::Serialize,
+    >(&self, query: &str, variables: V) -> Result<Res> {
+
+        let req = GraphQlRequest { query, variables };
+        let res: GraphQlResponse<Res> = self.make_request(reqwest::Method::POST, self.graphql_url.clone(), req, Vec::new(), ApiEndpoint::Other).await?;
+
+        let errors = res.errors.unwrap_or_default();
+        if let Some(not_found) = errors.iter().find(|e| e.extensions.as_ref().and_then(|x| x.code.as_deref()) == Some("NOT_FOUND")) {
+            return Err(FlapsError::NotFound(RawApiError {
+                status_code: 404,
+                fly_request_id: None,
+                error: not_found.message.clone(),
+                message: None,
+            }));
+        }
+        if !errors.is_empty() {
+            return Err(FlapsError::GraphQl(errors.into_iter().map(|e| e.message).collect()));
+        }
+
+        res.data.ok_or_else(|| FlapsError::GraphQl(vec!["response contained no data".to_string()]))
+    }
+}
@@ -0,0 +1,202 @@
+use thiserror::Error;
+
+use crate::entities::machine::{Config, ConfigErrors, Guest, Machine, Mount, Port, RestartPolicy, Service, SetSizeError, MACHINE_SCHEDULES};
+use super::{AsVolumeId, LaunchMachineInput};
+
+#[derive(Debug, Error)]
+pub enum LaunchMachineBuildError {
+    #[error("No image was set")]
+    MissingImage,
+    #[error(transparent)]
+    InvalidSize(#[from] SetSizeError),
+    #[error("A machine can only mount one volume, but {0} were added")]
+    TooManyMounts(usize),
+    #[error("Port 0 is not a valid port")]
+    InvalidPort,
+    #[error("invalid schedule '{0}', expected one of hourly, daily, weekly or monthly")]
+    InvalidSchedule(String),
+    /// The config failed [`Config::validate`] for a reason not covered by the variants above.
+    #[error("Invalid machine config: {0}")]
+    InvalidConfig(ConfigErrors),
+}
+
+/// Builds a [`LaunchMachineInput`] without filling in the machine [`Config`] by hand.
+///
+/// ```
+/// # use flyio_api::{api::flaps::LaunchMachineInput, entities::machine::RestartPolicy};
+/// let input = LaunchMachineInput::builder()
+///     .image("registry.fly.io/my-app:deployment-1")
+///     .region("ord")
+///     .size("shared-cpu-2x")
+///     .env("LOG_LEVEL", "info")
+///     .http_service(8080, &[80, 443])
+///     .mount("vol_4yl2v6o3d1r8xk9m", "/data")
+///     .restart(RestartPolicy::OnFailure)
+///     .build()
+///     .unwrap();
+/// assert_eq!(input.config.unwrap().guest.unwrap().cpus, 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LaunchMachineBuilder {
+    input: LaunchMachineInput,
+    config: Config,
+    size_error: Option<SetSizeError>,
+}
+
+impl LaunchMachineInput {
+    pub fn builder() -> LaunchMachineBuilder {
+        LaunchMachineBuilder::default()
+    }
+}
+
+impl LaunchMachineBuilder {
+    /// Starts from an existing config, keeping everything in it, including fields this crate doesn't know about.
+    pub fn from_config(config: Config) -> Self {
+        LaunchMachineBuilder { config, ..Default::default() }
+    }
+
+    /// Starts from a machine's current config, to pass to [`Client::update`](super::Client::update).
+    pub fn from_machine(machine: &Machine) -> Self {
+        let mut builder = Self::from_config(machine.config.clone().unwrap_or_default());
+        builder.input.id = Some(machine.id.clone());
+        builder.input.region = Some(machine.region.clone());
+        builder
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.config.image = image.into();
+        self
+    }
+
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.input.region = Some(region.into());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.input.name = Some(name.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.env.get_or_insert_with(Default::default).insert(key.into(), value.into());
+        self
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.metadata.get_or_insert_with(Default::default).insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the CPUs and memory from a preset such as `shared-cpu-2x`, see [`Guest::from_size`].
+    /// An unknown preset is reported by [`build`](Self::build).
+    pub fn size(mut self, size: &str) -> Self {
+        let guest = self.config.guest.get_or_insert_with(Guest::default);
+        if let Err(e) = guest.set_size(size) {
+            self.size_error = Some(e);
+        }
+        self
+    }
+
+    /// Adds a TCP service that forwards `ports` to `internal_port`, like `[http_service]` in `fly.toml`.
+    /// Port 443 terminates TLS, and other ports force HTTPS if 443 is one of the ports.
+    pub fn http_service(mut self, internal_port: u16, ports: &[u16]) -> Self {
+        let https = ports.contains(&443);
+        self.config.services.push(Service {
+            protocol: "tcp".to_string(),
+            internal_port: i32::from(internal_port),
+            ports: ports.iter().map(|&port| Port {
+                port: Some(port),
+                handlers: match port {
+                    443 => vec!["tls".to_string(), "http".to_string()],
+                    _ => vec!["http".to_string()],
+                },
+                force_https: https && port != 443,
+                ..Default::default()
+            }).collect(),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Adds a service that was built by hand.
+    pub fn service(mut self, service: Service) -> Self {
+        self.config.services.push(service);
+        self
+    }
+
+    /// Mounts a volume at `path`. A machine can only mount one volume.
+    pub fn mount<V: AsVolumeId>(mut self, volume: V, path: impl Into<String>) -> Self {
+        self.config.mounts.push(Mount::from_vol_id(volume.as_volume_id().to_string(), path.into()));
+        self
+    }
+
+    pub fn restart(mut self, policy: RestartPolicy) -> Self {
+        self.config.restart.policy = Some(policy);
+        self
+    }
+
+    /// Runs the machine on a schedule: `hourly`, `daily`, `weekly` or `monthly`.
+    pub fn schedule(mut self, schedule: impl Into<String>) -> Self {
+        self.config.schedule = Some(schedule.into());
+        self
+    }
+
+    /// Destroys the machine once it exits.
+    pub fn auto_destroy(mut self, auto_destroy: bool) -> Self {
+        self.config.auto_destroy = auto_destroy;
+        self
+    }
+
+    /// Checks the config with [`Config::validate`] after the checks that have their own error variants.
+    pub fn build(self) -> Result<LaunchMachineInput, LaunchMachineBuildError> {
+        let config = self.config;
+        if let Some(e) = self.size_error {
+            return Err(e.into());
+        }
+        if config.image.is_empty() {
+            return Err(LaunchMachineBuildError::MissingImage);
+        }
+        if config.mounts.len() > 1 {
+            return Err(LaunchMachineBuildError::TooManyMounts(config.mounts.len()));
+        }
+        let mut ports = config.services.iter().flat_map(|s| s.ports.iter().filter_map(|p| p.port.map(i32::from)).chain([s.internal_port]));
+        if ports.any(|p| p == 0) {
+            return Err(LaunchMachineBuildError::InvalidPort);
+        }
+        if let Some(schedule) = config.schedule.as_ref().filter(|s| !MACHINE_SCHEDULES.contains(&s.as_str())) {
+            return Err(LaunchMachineBuildError::InvalidSchedule(schedule.clone()));
+        }
+        config.validate().map_err(LaunchMachineBuildError::InvalidConfig)?;
+
+        Ok(LaunchMachineInput { config: Some(config), ..self.input })
+    }
+}
+
+#[test]
+fn test_launch_machine_builder() {
+    let input = LaunchMachineInput::builder()
+        .image("registry.fly.io/my-app:deployment-1")
+        .size("performance-2x")
+        .http_service(8080, &[80, 443])
+        .metadata("fly_process_group", "app")
+        .schedule("daily")
+        .build()
+        .unwrap();
+    let config = input.config.unwrap();
+    assert_eq!(config.guest.unwrap().memory_mb, 4096);
+    let ports = &config.services[0].ports;
+    assert_eq!((ports[0].force_https, ports[1].force_https), (true, false));
+    assert_eq!(ports[1].handlers, ["tls", "http"]);
+
+    let build = |builder: LaunchMachineBuilder| builder.build().unwrap_err();
+    assert!(matches!(build(LaunchMachineInput::builder()), LaunchMachineBuildError::MissingImage));
+    assert!(matches!(build(LaunchMachineInput::builder().image("a").size("shared-cpu-3x")), LaunchMachineBuildError::InvalidSize(_)));
+    assert!(matches!(build(LaunchMachineInput::builder().image("a").mount("vol_1", "/a").mount("vol_2", "/b")), LaunchMachineBuildError::TooManyMounts(2)));
+    assert!(matches!(build(LaunchMachineInput::builder().image("a").http_service(0, &[80])), LaunchMachineBuildError::InvalidPort));
+    assert!(matches!(build(LaunchMachineInput::builder().image("a").schedule("yearly")), LaunchMachineBuildError::InvalidSchedule(_)));
+    match build(LaunchMachineInput::builder().image("a").mount("vol_1", "data")) {
+        LaunchMachineBuildError::InvalidConfig(errors) => assert_eq!(errors[0].path, "mounts[0].path"),
+        other => panic!("expected an invalid config, got {other:?}"),
+    }
+}
@@ -0,0 +1,177 @@
+use std::{sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use super::{
+    observer::ActiveLease, Client, Result, AsMachineId, MachineLease, LaunchMachineInput, MachineStartResponse,
+    StopMachineInput, RemoveMachineInput,
+};
+use crate::entities;
+
+/// Refreshes are retried this often after a failure, until the lease expires.
+const REFRESH_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A machine lease that is kept alive in the background and released when dropped.
+///
+/// Created with [`Client::lease`]. The lease is refreshed on a tokio task once two thirds of
+/// its remaining lifetime has passed. Dropping the guard releases the lease on a spawned task;
+/// use [`release`](Self::release) to release it and observe the result.
+pub struct LeaseGuard {
+    client: Client,
+    machine_id: String,
+    ttl: Option<i32>,
+    lease: Arc<Mutex<MachineLease>>,
+    refresher: Option<tokio::task::JoinHandle<()>>,
+    released: bool,
+    /// Finished by the refresh task when the lease expires, or when the guard is dropped, which
+    /// includes [`release`](Self::release) and [`destroy`](Self::destroy).
+    active: Arc<ActiveLease>,
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// How long to wait before refreshing a lease that expires at `expires_at`.
+fn refresh_delay(expires_at: i64, now: i64) -> Duration {
+    let remaining = (expires_at - now).max(0) as u64;
+    Duration::from_secs(remaining - remaining / 3).max(Duration::from_secs(1))
+}
+
+impl Client {
+    /// Acquires a lease on `machine`, returning a guard that refreshes it until it is released.
+    /// `ttl` is in seconds; the server default is used when `None`.
+    pub async fn lease<M: AsMachineId>(&self, machine: M, ttl: Option<i32>) -> Result<LeaseGuard> {
+        let machine_id = machine.as_machine_id().to_string();
+        let lease = self.acquire_lease(&machine_id, ttl).await?;
+
+        let mut guard = LeaseGuard {
+            client: self.clone(),
+            machine_id,
+            ttl,
+            lease: Arc::new(Mutex::new(lease)),
+            refresher: None,
+            released: false,
+            active: Arc::new(ActiveLease::new(&self.raw.metrics)),
+        };
+        guard.refresher = Some(tokio::spawn(guard.refresh_loop()));
+        Ok(guard)
+    }
+}
+
+impl LeaseGuard {
+
+    fn refresh_loop(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let client = self.client.clone();
+        let machine_id = self.machine_id.clone();
+        let ttl = self.ttl;
+        let lease = self.lease.clone();
+        let active = self.active.clone();
+
+        async move {
+            loop {
+                let (nonce, expires_at) = {
+                    let lease = lease.lock().unwrap();
+                    (lease.data.nonce.clone(), lease.data.expires_at)
+                };
+                tokio::time::sleep(refresh_delay(expires_at, unix_now())).await;
+
+                loop {
+                    match client.refresh_lease(&machine_id, ttl, nonce.clone()).await {
+                        Ok(refreshed) => {
+                            *lease.lock().unwrap() = refreshed;
+                            break;
+                        },
+                        // Nothing left to keep alive; operations using the nonce will report the failure.
+                        Err(_) if unix_now() >= expires_at => {
+                            active.finish();
+                            return;
+                        },
+                        Err(_) => tokio::time::sleep(REFRESH_RETRY_INTERVAL).await,
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn machine_id(&self) -> &str {
+        &self.machine_id
+    }
+
+    /// The current lease, as of the last refresh.
+    pub fn lease(&self) -> MachineLease {
+        self.lease.lock().unwrap().clone()
+    }
+
+    /// The current lease nonce, to pass to any of the [`Client`] methods that take one.
+    pub fn nonce(&self) -> String {
+        self.lease.lock().unwrap().data.nonce.clone()
+    }
+
+    /// Updates the leased machine. The input's `id` is set to the leased machine.
+    pub async fn update(&self, mut input: LaunchMachineInput) -> Result<entities::machine::Machine> {
+        input.id = Some(self.machine_id.clone());
+        self.client.update(input, Some(self.nonce())).await
+    }
+
+    pub async fn start(&self) -> Result<MachineStartResponse> {
+        self.client.start(&self.machine_id, Some(self.nonce())).await
+    }
+
+    /// Stops the leased machine. The input's `id` is set to the leased machine.
+    pub async fn stop(&self, mut input: StopMachineInput) -> Result<()> {
+        input.id = self.machine_id.clone();
+        self.client.stop(input, Some(self.nonce())).await
+    }
+
+    /// Destroys the leased machine. The lease goes away with it, so this consumes the guard.
+    pub async fn destroy(mut self, kill: bool) -> Result<()> {
+        self.stop_refreshing();
+        let input = RemoveMachineInput { id: self.machine_id.clone(), kill };
+        let nonce = self.nonce();
+        let res = self.client.destroy(input, Some(nonce)).await;
+        // Don't try to release a lease on a machine that no longer exists.
+        self.released = res.is_ok();
+        res
+    }
+
+    /// Stops refreshing the lease and releases it.
+    pub async fn release(mut self) -> Result<()> {
+        self.stop_refreshing();
+        self.released = true;
+        self.client.release_lease(&self.machine_id, Some(self.nonce())).await
+    }
+
+    fn stop_refreshing(&mut self) {
+        if let Some(refresher) = self.refresher.take() {
+            refresher.abort();
+        }
+    }
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        self.stop_refreshing();
+        self.active.finish();
+        if self.released {
+            return;
+        }
+
+        // Without a runtime there's no way to release the lease; it will expire on its own.
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let client = self.client.clone();
+            let machine_id = std::mem::take(&mut self.machine_id);
+            let nonce = self.nonce();
+            handle.spawn(async move {
+                let _ = client.release_lease(&machine_id, Some(nonce)).await;
+            });
+        }
+    }
+}
+
+#[test]
+fn test_refresh_delay() {
+    assert_eq!(refresh_delay(1030, 1000), Duration::from_secs(20));
+    assert_eq!(refresh_delay(1002, 1000), Duration::from_secs(2));
+    // Refresh soon, rather than spinning, on expired or nearly expired leases.
+    assert_eq!(refresh_delay(1000, 1000), Duration::from_secs(1));
+    assert_eq!(refresh_delay(900, 1000), Duration::from_secs(1));
+}
@@ -0,0 +1,81 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::{sync::{Semaphore, SemaphorePermit}, time::Instant};
+
+/// A token bucket: up to `burst` requests can be sent at once, refilled at `requests_per_second`.
+/// Limits with a `requests_per_second` of zero or less are ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+struct Bucket {
+    /// Goes negative when requests are waiting for tokens that haven't been refilled yet.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Throttles requests from every clone of a client. Held by the client's `RawClient`.
+pub(super) struct Limiter {
+    rate: Option<(RateLimit, Mutex<Bucket>)>,
+    concurrency: Option<(usize, Semaphore)>,
+}
+
+impl Limiter {
+    pub(super) fn new(rate_limit: Option<RateLimit>, max_concurrency: Option<usize>) -> Self {
+        Limiter {
+            rate: rate_limit.filter(|limit| limit.requests_per_second > 0.0).map(|limit| (limit, Mutex::new(Bucket {
+                tokens: f64::from(limit.burst.max(1)),
+                refilled_at: Instant::now(),
+            }))),
+            concurrency: max_concurrency.map(|n| (n.max(1), Semaphore::new(n.max(1)))),
+        }
+    }
+
+    pub(super) fn max_concurrency(&self) -> Option<usize> {
+        self.concurrency.as_ref().map(|(n, _)| *n)
+    }
+
+    /// Waits for a token and a free slot. The slot is held until the returned permit is dropped.
+    pub(super) async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if let Some(delay) = self.reserve_token(Instant::now()) {
+            tokio::time::sleep(delay).await;
+        }
+        match &self.concurrency {
+            // The semaphore is never closed
+            Some((_, semaphore)) => semaphore.acquire().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Takes a token from the bucket, returning how long to wait until it's actually available.
+    fn reserve_token(&self, now: Instant) -> Option<Duration> {
+        let (limit, bucket) = self.rate.as_ref()?;
+        let mut bucket = bucket.lock().unwrap();
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.requests_per_second).min(f64::from(limit.burst.max(1)));
+        bucket.refilled_at = now;
+
+        bucket.tokens -= 1.0;
+        (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / limit.requests_per_second))
+    }
+}
+
+#[test]
+fn test_reserve_token() {
+    let limiter = Limiter::new(Some(RateLimit { requests_per_second: 2.0, burst: 2 }), None);
+    let start = Instant::now();
+
+    assert_eq!(limiter.reserve_token(start), None);
+    assert_eq!(limiter.reserve_token(start), None);
+    // Out of tokens; each further request waits for one more refill
+    assert_eq!(limiter.reserve_token(start), Some(Duration::from_millis(500)));
+    assert_eq!(limiter.reserve_token(start), Some(Duration::from_millis(1000)));
+    // After a long pause the bucket is full again, but never holds more than `burst` tokens
+    let later = start + Duration::from_secs(10);
+    assert_eq!(limiter.reserve_token(later), None);
+    assert_eq!(limiter.reserve_token(later), None);
+    assert_eq!(limiter.reserve_token(later), Some(Duration::from_millis(500)));
+}
@@ -1,4 +1,4 @@
-use std::{fmt::Display, time::Duration, sync::Arc};
+use std::{collections::BTreeMap, fmt::Display, time::Duration, sync::Arc};
 
 use thiserror::Error;
 
@@ -7,9 +7,38 @@ pub use types::*;
 use crate::entities;
 
 mod transport;
-use transport::*;
+pub use transport::{Transport, TransportResult, HeaderPair, HttpTransport};
+#[cfg(feature = "unix-socket")]
+pub use transport::UnixSocketTransport;
 #[cfg(feature = "unix-socket")]
 mod unix;
+#[cfg(feature = "unix-socket")]
+pub use unix::DEFAULT_SOCKET_PATH;
+
+mod volumes;
+pub use volumes::VolumesClient;
+mod apps;
+pub use apps::OrgClient;
+mod orgs;
+mod graphql;
+mod secrets;
+mod lease;
+pub use lease::LeaseGuard;
+mod launch;
+pub use launch::{LaunchMachineBuilder, LaunchMachineBuildError};
+mod watch;
+pub use watch::MachineWatchEvent;
+mod retry;
+pub use retry::RetryPolicy;
+mod settings;
+mod trace;
+mod limit;
+pub use limit::RateLimit;
+mod observer;
+pub use observer::{MetricsObserver, NoopMetricsObserver, RequestInfo, RequestOutcome};
+#[cfg(feature = "metrics")]
+pub use observer::MetricsCrateObserver;
+pub use settings::{FlapsSettings, FlapsSettingsBuilder};
 
 pub type Result<T> = std::result::Result<T, FlapsError>;
 
@@ -19,13 +48,27 @@ pub enum FlapsClientCreationError {
     MissingAppName,
     #[error("Invalid app name")]
     InvalidAppName(String),
+    #[error("Invalid organization slug")]
+    InvalidOrgSlug(String),
     #[error("Invalid base url: {0}")]
     InvalidBaseUrl(#[from] url::ParseError),
+    #[error("Missing auth token")]
+    MissingAuthToken,
+    #[error("A proxy or connect timeout can't be applied to an existing HTTP client")]
+    ConflictingHttpSettings,
+    #[error("Failed to build HTTP client: {0}")]
+    HttpClient(#[from] reqwest::Error),
+    #[cfg(feature = "unix-socket")]
+    #[error("A proxy, connect timeout or HTTP client can't be used with the unix socket transport")]
+    HttpSettingsOnSocket,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct RawApiError {
+    // Not part of the response body; filled in from the response itself
+    #[serde(default)]
     pub status_code: u16,
+    #[serde(default)]
     pub fly_request_id: Option<String>,
     pub error: String,
     pub message: Option<String>,
@@ -49,8 +92,13 @@ pub enum FlapsError {
     Http(#[from] http::Error),
     #[error("HTTP error: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[cfg(feature = "unix-socket")]
     #[error("Socket transport error: {0}")]
     Hyper(#[from] hyper::Error),
+    /// A request through the unix socket took longer than [`FlapsSettingsBuilder::timeout`].
+    #[cfg(feature = "unix-socket")]
+    #[error("Request timed out")]
+    Timeout,
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
@@ -66,17 +114,160 @@ pub enum FlapsError {
     #[error("Not found")]
     NotFound(RawApiError),
 
+    #[error("Unauthorized: {0}")]
+    Unauthorized(RawApiError),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(RawApiError),
+
+    #[error("Conflict: {0}")]
+    Conflict(RawApiError),
+
+    /// A precondition of the request wasn't met, most commonly a missing or mismatched lease nonce.
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(RawApiError),
+
+    #[error("Invalid request: {raw}")]
+    Validation{raw: RawApiError, details: Vec<FieldError>},
+
+    #[error("Rate limited: {raw}")]
+    RateLimited{raw: RawApiError, retry_after: Option<Duration>},
+
+    #[error("Server error: {0}")]
+    ServerError(RawApiError),
+
+    /// The config failed [`Config::validate`](entities::machine::Config::validate), so the request wasn't sent.
+    #[error("Invalid machine config: {0}")]
+    InvalidConfig(entities::machine::ConfigErrors),
+
+    #[error("GraphQL error: {}", .0.join("; "))]
+    GraphQl(Vec<String>),
+
+    #[error("Can't wait for a machine to reach state '{0}'")]
+    InvalidWaitState(crate::entities::machine::State),
+
     #[error("Timed out waiting for machine to reach desired state '{desired_state}'")]
     DesiredStateNotReached{desired_state: crate::entities::machine::State, raw: RawApiError},
 }
 
+/// A problem with one field of a request, from the `details` of a 422 response.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct FieldError {
+    #[serde(alias = "path")]
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ValidationDetails {
+    #[serde(default)]
+    details: Vec<FieldError>,
+}
+
+impl FlapsError {
+    /// The error response from the API, for errors that came with one.
+    pub fn raw(&self) -> Option<&RawApiError> {
+        match self {
+            FlapsError::UnknownFlapsError(raw)
+            | FlapsError::NotFound(raw)
+            | FlapsError::Unauthorized(raw)
+            | FlapsError::Forbidden(raw)
+            | FlapsError::Conflict(raw)
+            | FlapsError::PreconditionFailed(raw)
+            | FlapsError::ServerError(raw)
+            | FlapsError::Validation{raw, ..}
+            | FlapsError::RateLimited{raw, ..}
+            | FlapsError::DesiredStateNotReached{raw, ..} => Some(raw),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status of the response, if the error came from one.
+    pub fn status(&self) -> Option<http::StatusCode> {
+        match self {
+            FlapsError::UnexpectedHttpStatus(status) => Some(*status),
+            FlapsError::Reqwest(e) => e.status(),
+            _ => self.raw().and_then(|raw| http::StatusCode::from_u16(raw.status_code).ok()),
+        }
+    }
+
+    /// The `fly-request-id` of the response, to quote when reporting problems to Fly.io.
+    pub fn request_id(&self) -> Option<&str> {
+        self.raw().and_then(|raw| raw.fly_request_id.as_deref())
+    }
+
+    /// Whether the same request might succeed if it's sent again: the statuses the default [`RetryPolicy`]
+    /// retries (429, 502, 503 and 504), and connections that failed or timed out. This doesn't consider
+    /// whether repeating the request is safe; the [`RetryPolicy`] decides that.
+    pub fn is_retryable(&self) -> bool {
+        match self.status() {
+            Some(status) => retry::DEFAULT_RETRYABLE_STATUSES.contains(&status),
+            None => self.is_connection_error() || self.is_interrupted(),
+        }
+    }
+
+    /// A short, stable name for the kind of error, e.g. `not_found` or `rate_limited`, for use in metrics and logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FlapsError::InvalidUrl(_) => "invalid_url",
+            FlapsError::Http(_) => "http",
+            FlapsError::Reqwest(_) if self.is_connection_error() => "connection",
+            FlapsError::Reqwest(_) => "transport",
+            #[cfg(feature = "unix-socket")]
+            FlapsError::Hyper(_) if self.is_connection_error() => "connection",
+            #[cfg(feature = "unix-socket")]
+            FlapsError::Hyper(_) => "transport",
+            #[cfg(feature = "unix-socket")]
+            FlapsError::Timeout => "timeout",
+            FlapsError::Json(_) => "json",
+            FlapsError::UnexpectedHttpStatus(_) => "unexpected_status",
+            FlapsError::UnknownFlapsError(_) => "unknown",
+            FlapsError::NoMachineId => "no_machine_id",
+            FlapsError::NotFound(_) => "not_found",
+            FlapsError::Unauthorized(_) => "unauthorized",
+            FlapsError::Forbidden(_) => "forbidden",
+            FlapsError::Conflict(_) => "conflict",
+            FlapsError::PreconditionFailed(_) => "precondition_failed",
+            FlapsError::Validation{..} => "validation",
+            FlapsError::RateLimited{..} => "rate_limited",
+            FlapsError::ServerError(_) => "server_error",
+            FlapsError::InvalidConfig(_) => "invalid_config",
+            FlapsError::GraphQl(_) => "graphql",
+            FlapsError::InvalidWaitState(_) => "invalid_wait_state",
+            FlapsError::DesiredStateNotReached{..} => "desired_state_not_reached",
+        }
+    }
+
+    /// The request couldn't be sent at all, so the server can't have acted on it.
+    pub(super) fn is_connection_error(&self) -> bool {
+        match self {
+            FlapsError::Reqwest(e) => e.is_connect(),
+            #[cfg(feature = "unix-socket")]
+            FlapsError::Hyper(e) => e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// The request was sent, but no complete response came back.
+    pub(super) fn is_interrupted(&self) -> bool {
+        match self {
+            FlapsError::Reqwest(e) => !e.is_connect() && (e.is_timeout() || e.is_request() || e.is_body()),
+            #[cfg(feature = "unix-socket")]
+            FlapsError::Hyper(e) => !e.is_connect() && (e.is_incomplete_message() || e.is_closed() || e.is_timeout()),
+            #[cfg(feature = "unix-socket")]
+            FlapsError::Timeout => true,
+            _ => false,
+        }
+    }
+}
+
 // Used to determine the behavior of [`map_flaps_error`]
 enum ApiEndpoint {
     Other,
     Wait(crate::entities::machine::State),
 }
 
-fn map_flaps_error(body: bytes::Bytes, fly_request_id: Option<String>, status: reqwest::StatusCode, endpoint: ApiEndpoint) -> FlapsError {
+fn map_flaps_error(body: bytes::Bytes, fly_request_id: Option<String>, status: reqwest::StatusCode, headers: &http::HeaderMap, endpoint: ApiEndpoint) -> FlapsError {
 
     if status.is_success() {
         unreachable!("map_flaps_error called with success status code")
@@ -93,14 +284,15 @@ fn map_flaps_error(body: bytes::Bytes, fly_request_id: Option<String>, status: r
 
     let status_code = status.as_u16();
 
-    let raw_api_err: RawApiError = serde_json::from_slice(&body).unwrap_or_else(|_| RawApiError {
-        error: format!("Server returned non-2xx status code {}, raw response: {:?}", status, body),
-        message: None,
-        fly_request_id,
-        status_code,
-    });
-
-    // Attempt to use string comparison to map errors to strong types
+    let raw_api_err = match serde_json::from_slice::<RawApiError>(&body) {
+        Ok(raw) => RawApiError { status_code, fly_request_id, ..raw },
+        Err(_) => RawApiError {
+            error: format!("Server returned non-2xx status code {}, raw response: {:?}", status, body),
+            message: None,
+            fly_request_id,
+            status_code,
+        },
+    };
 
     match endpoint {
         ApiEndpoint::Wait(desired_state) => {
@@ -112,35 +304,196 @@ fn map_flaps_error(body: bytes::Bytes, fly_request_id: Option<String>, status: r
     }
 
 
-    if status == reqwest::StatusCode::NOT_FOUND {
-        return FlapsError::NotFound(raw_api_err);
+    match status {
+        http::StatusCode::NOT_FOUND => FlapsError::NotFound(raw_api_err),
+        http::StatusCode::UNAUTHORIZED => FlapsError::Unauthorized(raw_api_err),
+        http::StatusCode::FORBIDDEN => FlapsError::Forbidden(raw_api_err),
+        http::StatusCode::CONFLICT => FlapsError::Conflict(raw_api_err),
+        http::StatusCode::PRECONDITION_FAILED => FlapsError::PreconditionFailed(raw_api_err),
+        http::StatusCode::UNPROCESSABLE_ENTITY => FlapsError::Validation {
+            raw: raw_api_err,
+            details: serde_json::from_slice::<ValidationDetails>(&body).map(|v| v.details).unwrap_or_default(),
+        },
+        http::StatusCode::TOO_MANY_REQUESTS => FlapsError::RateLimited {
+            raw: raw_api_err,
+            retry_after: retry::retry_after(headers),
+        },
+        _ if status.is_server_error() => FlapsError::ServerError(raw_api_err),
+        _ => FlapsError::UnknownFlapsError(raw_api_err),
     }
-    
+}
 
-    FlapsError::UnknownFlapsError(raw_api_err)
+/// Connection state shared by every client created from the same settings.
+struct RawClient {
+    client: Arc<dyn Transport>,
+    base_url: url::Url,
+    graphql_url: url::Url,
+    user_agent: String,
+    retry_policy: RetryPolicy,
+    metrics: Arc<dyn MetricsObserver>,
+    limiter: limit::Limiter,
+    validate_configs: bool,
 }
 
-pub struct FlapsSettings {
-    base_url: Option<String>,
-    user_agent: Option<String>,
-    auth_token: String,
-    app_name: Option<String>,
+impl RawClient {
+
+    fn new_http(mut cfg: FlapsSettings) -> std::result::Result<RawClient, FlapsClientCreationError> {
+        if cfg.auth_token.is_empty() {
+            return Err(FlapsClientCreationError::MissingAuthToken);
+        }
+
+        let http_client = match cfg.http_client.take() {
+            Some(_) if cfg.proxy.is_some() || cfg.connect_timeout.is_some() => {
+                return Err(FlapsClientCreationError::ConflictingHttpSettings);
+            },
+            Some(client) => client,
+            None => {
+                let mut builder = reqwest::Client::builder();
+                if let Some(proxy) = cfg.proxy.take() {
+                    builder = builder.proxy(proxy);
+                }
+                if let Some(connect_timeout) = cfg.connect_timeout {
+                    builder = builder.connect_timeout(connect_timeout);
+                }
+                builder.build()?
+            },
+        };
+
+        let mut transport = HttpTransport::new(http_client, &cfg.auth_token)
+            .with_default_headers(std::mem::take(&mut cfg.default_headers));
+        if let Some(timeout) = cfg.timeout {
+            transport = transport.with_timeout(timeout);
+        }
+        Self::with_transport(cfg, Arc::new(transport))
+    }
+
+    /// Unlike over HTTP, the auth token is optional; the socket authenticates requests itself.
+    #[cfg(feature = "unix-socket")]
+    fn new_unix_socket(mut cfg: FlapsSettings) -> std::result::Result<RawClient, FlapsClientCreationError> {
+        if cfg.proxy.is_some() || cfg.connect_timeout.is_some() || cfg.http_client.is_some() {
+            return Err(FlapsClientCreationError::HttpSettingsOnSocket);
+        }
+
+        let path = cfg.socket_path.take().unwrap_or_else(|| DEFAULT_SOCKET_PATH.into());
+        let mut transport = UnixSocketTransport::with_path(path)
+            .with_default_headers(std::mem::take(&mut cfg.default_headers));
+        if !cfg.auth_token.is_empty() {
+            transport = transport.with_auth_token(&cfg.auth_token);
+        }
+        if let Some(timeout) = cfg.timeout {
+            transport = transport.with_timeout(timeout);
+        }
+
+        // Hostname unused, just has to exist. We use the unix socket to route.
+        cfg.base_url.get_or_insert_with(|| "http://localhost".to_string());
+        cfg.user_agent.get_or_insert_with(|| format!("flyio-api-rs-unix/{}", env!("CARGO_PKG_VERSION")));
+        Self::with_transport(cfg, Arc::new(transport))
+    }
+
+    fn with_transport(cfg: FlapsSettings, client: Arc<dyn Transport>) -> std::result::Result<RawClient, FlapsClientCreationError> {
+
+        let base_url = reqwest::Url::parse(&cfg.base_url.unwrap_or_else(default_base_url))?;
+        let graphql_url = reqwest::Url::parse(&cfg.graphql_url.unwrap_or_else(default_graphql_url))?;
+
+        Ok(RawClient {
+            client,
+            base_url,
+            graphql_url,
+            user_agent: cfg.user_agent.unwrap_or_else(|| format!("flyio-api-rs/{}", env!("CARGO_PKG_VERSION"))),
+            retry_policy: cfg.retry_policy.unwrap_or_default(),
+            metrics: cfg.metrics_observer.unwrap_or_else(|| Arc::new(NoopMetricsObserver)),
+            limiter: limit::Limiter::new(cfg.rate_limit, cfg.max_concurrency),
+            validate_configs: cfg.validate_configs,
+        })
+    }
+
+    async fn make_request_raw(&self, method: reqwest::Method, url: reqwest::Url, json: String, headers: Vec<HeaderPair>, api_endpoint: ApiEndpoint) -> Result<bytes::Bytes> {
+
+        let info = RequestInfo::new(&method, &url);
+        self.metrics.request_started(&info);
+
+        let started = std::time::Instant::now();
+        let mut outcome = RequestOutcome { status: None, error: None, latency: Duration::ZERO, attempts: 0 };
+        let res = self.send_with_retries(method, url, json, headers, api_endpoint, &mut outcome).await;
+
+        outcome.latency = started.elapsed();
+        outcome.error = res.as_ref().err()
+            .filter(|e| !matches!(e, FlapsError::DesiredStateNotReached{..}))
+            .map(FlapsError::kind);
+        self.metrics.request_finished(&info, &outcome);
+        res
+    }
+
+    async fn send_with_retries(&self, method: reqwest::Method, url: reqwest::Url, json: String, headers: Vec<HeaderPair>, api_endpoint: ApiEndpoint, outcome: &mut RequestOutcome) -> Result<bytes::Bytes> {
+
+        let span = trace::RequestSpan::new(&method, &url);
+        let mut backoff = self.retry_policy.backoff();
+        let res = span.instrument(async {
+            loop {
+                outcome.attempts += 1;
+                let permit = self.limiter.acquire().await;
+                let started = std::time::Instant::now();
+                let res = self.client.make_request(&self.user_agent, method.clone(), url.clone(), json.clone(), headers.clone()).await;
+                span.record_attempt(outcome.attempts, &res, started.elapsed());
+                drop(permit);
+                outcome.status = res.as_ref().ok().map(|r| r.status_code);
+                match self.retry_policy.retry_delay(outcome.attempts, &method, &res, &mut backoff) {
+                    Some(delay) => {
+                        span.record_retry(outcome.attempts, delay);
+                        tokio::time::sleep(delay).await;
+                    },
+                    None => break res,
+                }
+            }
+        }).await?;
+        let TransportResult {body, status_code, request_id, headers} = res;
+
+        if status_code.as_u16() > 299 {
+            return Err(map_flaps_error(body, request_id, status_code, &headers, api_endpoint));
+        }
+
+        // Some endpoints reply with an empty body on success; treat that as `null`.
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(bytes::Bytes::from_static(b"null"));
+        }
+
+        Ok(body)
+    }
+
+    async fn make_request<
+        Res: serde::de::DeserializeOwned,
+        Req: serde::Serialize,
+    >(
+        &self,
+        method: reqwest::Method,
+        url: url::Url,
+        data: Req,
+        headers: Vec<HeaderPair>,
+        api_endpoint: ApiEndpoint,
+    ) -> Result<Res> {
+
+        let json = serde_json::to_string(&data)?;
+        let bytes = self.make_request_raw(method, url, json, headers, api_endpoint).await?;
+        let response = serde_json::from_slice(&bytes)?;
+        Ok(response)
+    }
 }
 
-struct RawClient {
-    client: Transport,
-    // base_url: reqwest::Url,
+/// Client for the Machines API of a single app.
+///
+/// Cloning is cheap, and clones share the same transport.
+#[derive(Clone)]
+pub struct Client {
+    raw: Arc<RawClient>,
+    app_name: String,
+    /// `v1/apps/{app_name}/`, including the trailing slash so that resources can be joined onto it.
     app_url: url::Url,
-    user_agent: String,
-    // app_name: String,
 }
 
-pub struct Client(Arc<RawClient>);
-
 fn default_base_url() -> String {
-    match std::env::var("FLY_FLAPS_BASE_URL") {
-        Ok(url) => url,
-        Err(_) => {
+    match crate::api::env::flaps_base_url() {
+        Some(url) => url,
+        None => {
             match crate::api::env::running_on_fly() {
                 true => "http://_api.internal:4280".to_string(),
                 false => "https://api.machines.dev".to_string(),
@@ -149,19 +502,32 @@ fn default_base_url() -> String {
     }
 }
 
-const PROXY_TIMEOUT_THRESHOLD: Duration = Duration::from_secs(60);
+fn default_graphql_url() -> String {
+    let base = std::env::var("FLY_API_BASE_URL").unwrap_or_else(|_| "https://api.fly.io".to_string());
+    format!("{}/graphql", base.trim_end_matches('/'))
+}
 
-pub(crate) struct HeaderPair(&'static str, String);
-impl HeaderPair {
-    fn lease_nonce(nonce: String) -> HeaderPair {
-        HeaderPair("fly-machine-lease-nonce", nonce)
+fn validate_app_name(app_name: String) -> std::result::Result<String, FlapsClientCreationError> {
+    if app_name.is_empty() || app_name.chars().any(|c| matches!(c, '/'|':'|'\\'|'?'|'#')) {
+        return Err(FlapsClientCreationError::InvalidAppName(app_name));
     }
+    Ok(app_name)
 }
+
+const PROXY_TIMEOUT_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// How many requests helpers like [`Client::get_many`] send at once when there's no concurrency limit.
+const DEFAULT_FAN_OUT: usize = 16;
+
 fn add_lease_nonce(headers: &mut Vec<HeaderPair>, nonce: Option<String>) {
     if let Some(nonce) = nonce {
         headers.push(HeaderPair::lease_nonce(nonce));
     }
 }
+/// Used with `.map()` for endpoints whose success response carries nothing we care about,
+/// e.g. `{"ok": true}`, which can't be deserialized into `()`.
+fn ignore_body(_: serde::de::IgnoredAny) {}
+
 struct UrlParam<'a, 'b>(&'a str, &'b str);
 fn encode_url_params(params: &[UrlParam]) -> String {
     if params.is_empty() {
@@ -177,60 +543,55 @@ fn encode_url_params(params: &[UrlParam]) -> String {
 impl Client {
 
     pub fn new(cfg: FlapsSettings) -> std::result::Result<Client, FlapsClientCreationError> {
+        let app_name = cfg.app_name.clone().or_else(crate::api::env::current_app_name).ok_or(FlapsClientCreationError::MissingAppName)?;
+        Self::from_raw(Arc::new(RawClient::new_http(cfg)?), app_name)
+    }
 
-        let base_url = reqwest::Url::parse(&cfg.base_url.unwrap_or_else(default_base_url))?;
-        let app_name = cfg.app_name.or_else(crate::api::env::current_app_name).ok_or(FlapsClientCreationError::MissingAppName)?;
-
-        if app_name.chars().any(|c| matches!(c, '/'|':'|'\\')) {
-            return Err(FlapsClientCreationError::InvalidAppName(app_name));
-        }
-
-        let auth_header = if cfg.auth_token.starts_with("FlyV1 ") {
-            cfg.auth_token
-        } else {
-            format!("Bearer {}", cfg.auth_token)
-        };
-
-        Ok(Client(Arc::new(RawClient {
-            client: HttpTransport(reqwest::Client::new(), auth_header).into(),
-            app_url: base_url.join(format!("v1/apps/{}/machines", &app_name).as_str())?,
-            // base_url,
-            user_agent: cfg.user_agent.unwrap_or_else(|| format!("flyio-api-rs/{}", env!("CARGO_PKG_VERSION"))),
-            // app_name,
-        })))
+    /// Creates a client that sends its requests through a custom [`Transport`].
+    /// The settings' auth token is unused; the transport is responsible for authentication.
+    pub fn with_transport(cfg: FlapsSettings, transport: Arc<dyn Transport>) -> std::result::Result<Client, FlapsClientCreationError> {
+        let app_name = cfg.app_name.clone().or_else(crate::api::env::current_app_name).ok_or(FlapsClientCreationError::MissingAppName)?;
+        Self::from_raw(Arc::new(RawClient::with_transport(cfg, transport)?), app_name)
     }
 
+    /// Creates a client for the Machines API socket inside a Fly machine, at [`DEFAULT_SOCKET_PATH`].
     #[cfg(feature = "unix-socket")]
-    // TODO: Test this!
     pub fn new_from_socket(app_name: Option<String>) -> std::result::Result<Client, FlapsClientCreationError> {
-        let app_name = app_name.or_else(crate::api::env::current_app_name).ok_or(FlapsClientCreationError::MissingAppName)?;
-        // Hostname unused, just has to exist. We use the unix socket to route.
-        let base_url = url::Url::parse("http://localhost").unwrap();
-
-        if app_name.chars().any(|c| matches!(c, '/'|':'|'\\')) {
-            return Err(FlapsClientCreationError::InvalidAppName(app_name));
-        }
-        let client = hyper::Client::builder().build(unix::UnixSocketConnector);
+        Self::new_unix_socket(FlapsSettings { app_name, ..Default::default() })
+    }
 
-        Ok(Client(Arc::new(RawClient {
-            client: UnixSocketTransport(client).into(),
-            app_url: base_url.join(format!("v1/apps/{}/machines", &app_name).as_str())?,
-            // base_url,
-            user_agent: format!("flyio-api-rs-unix/{}", env!("CARGO_PKG_VERSION")),
-            // app_name,
-        })))
+    /// Creates a client that talks to the Machines API over a unix socket, at the settings'
+    /// [`socket_path`](FlapsSettingsBuilder::socket_path) or [`DEFAULT_SOCKET_PATH`].
+    ///
+    /// Request URLs are still built from the base URL (`http://localhost` by default), but its host is never
+    /// resolved: every request goes to the socket. The auth token is optional. The timeout and default headers
+    /// apply as they do over HTTP; a proxy, connect timeout or custom HTTP client is an error.
+    #[cfg(feature = "unix-socket")]
+    pub fn new_unix_socket(cfg: FlapsSettings) -> std::result::Result<Client, FlapsClientCreationError> {
+        let app_name = cfg.app_name.clone().or_else(crate::api::env::current_app_name).ok_or(FlapsClientCreationError::MissingAppName)?;
+        Self::from_raw(Arc::new(RawClient::new_unix_socket(cfg)?), app_name)
     }
 
-    async fn make_request_raw(&self, method: reqwest::Method, url: reqwest::Url, json: String, headers: Vec<HeaderPair>, api_endpoint: ApiEndpoint) -> Result<bytes::Bytes> {
+    fn from_raw(raw: Arc<RawClient>, app_name: String) -> std::result::Result<Client, FlapsClientCreationError> {
+        let app_name = validate_app_name(app_name)?;
+        let app_url = raw.base_url.join(format!("v1/apps/{}/", &app_name).as_str())?;
+        Ok(Client { raw, app_name, app_url })
+    }
 
-        let res = self.0.client.make_request(&self.0.user_agent, method, url, json, headers).await?;
-        let TransportResult {body, status_code, request_id} = res;
+    /// Creates a client for another app, sharing this client's transport and credentials.
+    pub fn for_app<A: AsAppName>(&self, app: &A) -> std::result::Result<Client, FlapsClientCreationError> {
+        Self::from_raw(self.raw.clone(), app.as_app_name().to_string())
+    }
 
-        if status_code.as_u16() > 299 {
-            return Err(map_flaps_error(body, request_id, status_code, api_endpoint));
-        }
+    pub fn app_name(&self) -> &str {
+        &self.app_name
+    }
 
-        Ok(body)
+    /// Builds the URL for an app-scoped resource, e.g. `("machines", "{id}/start")`.
+    /// The endpoint may be empty, or begin with a query string.
+    fn app_resource_url(&self, resource: &str, endpoint: &str) -> Result<url::Url> {
+        let sep = if endpoint.is_empty() || endpoint.starts_with('?') { "" } else { "/" };
+        Ok(self.app_url.join(&format!("{resource}{sep}{endpoint}"))?)
     }
 
     async fn make_machines_request<
@@ -244,11 +605,7 @@ impl Client {
         headers: Vec<HeaderPair>,
         api_endpoint: ApiEndpoint,
     ) -> Result<Res> {
-
-        let json = serde_json::to_string(&data)?;
-        let bytes = self.make_request_raw(method, self.0.app_url.join(endpoint)?, json, headers, api_endpoint).await?;
-        let response = serde_json::from_slice(&bytes)?;
-        Ok(response)
+        self.raw.make_request(method, self.app_resource_url("machines", endpoint)?, data, headers, api_endpoint).await
     }
 
     async fn make_machines_request_into<
@@ -265,16 +622,27 @@ impl Client {
     ) -> Result<()> {
 
         let json = serde_json::to_string(&data)?;
-        let bytes = self.make_request_raw(method, self.0.app_url.join(endpoint)?, json, headers, api_endpoint).await?;
+        let bytes = self.raw.make_request_raw(method, self.app_resource_url("machines", endpoint)?, json, headers, api_endpoint).await?;
         let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
         Res::deserialize_in_place(&mut deserializer, res)?;
         Ok(())
     }
 
+    fn validate_config(&self, req: &LaunchMachineInput) -> Result<()> {
+        match (self.raw.validate_configs, &req.config) {
+            (true, Some(config)) => config.validate().map_err(FlapsError::InvalidConfig),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the config first if [`FlapsSettingsBuilder::validate_configs`] is set.
     pub async fn launch(&self, req: LaunchMachineInput) -> Result<entities::machine::Machine> {
+        self.validate_config(&req)?;
         self.make_machines_request(reqwest::Method::POST, "", req, Vec::new(), ApiEndpoint::Other).await
     }
+    /// Checks the config first if [`FlapsSettingsBuilder::validate_configs`] is set.
     pub async fn update(&self, req: LaunchMachineInput, nonce: Option<String>) -> Result<entities::machine::Machine> {
+        self.validate_config(&req)?;
         let mut headers = Vec::new();
         add_lease_nonce(&mut headers, nonce);
 
@@ -288,7 +656,8 @@ impl Client {
 
         self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/start"), (), headers, ApiEndpoint::Other).await
     }
-    /// Waits for a machine to reach a state.
+    /// Waits for a machine to reach a state: `started` (the default), `stopped`, `suspended` or `destroyed`.
+    /// Any other state fails with [`FlapsError::InvalidWaitState`].
     /// The timeout is clamped between one second and [`PROXY_TIMEOUT_THRESHOLD`].
     /// If you want a longer timeout, use [`wait_for_state`], which calls this method repeatedly until the timeout is reached or the machine reaches the desired state.
     pub async fn wait(&self, machine: &entities::machine::Machine, state: Option<entities::machine::State>, timeout: Duration) -> Result<()> {
@@ -296,6 +665,10 @@ impl Client {
         let machine_id = &machine.id;
 
         let state = state.unwrap_or(entities::machine::State::Started);
+        if !state.is_waitable() {
+            return Err(FlapsError::InvalidWaitState(state));
+        }
+        let _wait = observer::WaitGuard::new(&self.raw.metrics);
         let mut version: &str = &machine.instance_id;
         if let Some(ver) = machine.version.as_deref() {
             version = ver;
@@ -305,21 +678,19 @@ impl Client {
 
         let wait_query = encode_url_params(&[
             UrlParam("instance_id", version),
-            UrlParam("state", &state.name()),
+            UrlParam("state", state.name()),
             UrlParam("timeout", &timeout_secs.to_string()),
         ]);
 
-        self.make_machines_request(reqwest::Method::GET, &format!("{machine_id}/wait{wait_query}"), (), Vec::new(), ApiEndpoint::Wait(state)).await
+        self.make_machines_request(reqwest::Method::GET, &format!("{machine_id}/wait{wait_query}"), (), Vec::new(), ApiEndpoint::Wait(state)).await.map(ignore_body)
         
     }
 
     pub async fn wait_for_state(&self, machine: &entities::machine::Machine, state: Option<entities::machine::State>, timeout: Duration) -> Result<()> {
-        let n = backoff::ExponentialBackoffBuilder::new()
-            .with_initial_interval(Duration::from_millis(500))
-            .with_max_interval(Duration::from_millis(5000))
-            .with_multiplier(1.5)
-            .with_max_elapsed_time(Some(timeout))
-            .build();
+        let n = backoff::ExponentialBackoff {
+            max_elapsed_time: Some(timeout),
+            ..self.raw.retry_policy.backoff()
+        };
 
         let end_time = std::time::Instant::now() + timeout + Duration::from_millis(100);
 
@@ -345,11 +716,21 @@ impl Client {
     }
 
 
+    /// Suspends a machine, snapshotting its memory so that the next start resumes it.
+    pub async fn suspend<M: AsMachineId>(&self, machine: M, nonce: Option<String>) -> Result<()> {
+        let machine_id = machine.as_machine_id();
+
+        let mut headers = Vec::new();
+        add_lease_nonce(&mut headers, nonce);
+
+        self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/suspend"), (), headers, ApiEndpoint::Other).await.map(ignore_body)
+    }
+
     pub async fn stop(&self, stop_input: StopMachineInput, nonce: Option<String>) -> Result<()> {
         let mut headers = Vec::new();
         add_lease_nonce(&mut headers, nonce);
 
-        self.make_machines_request(reqwest::Method::POST, &format!("{}/stop", stop_input.id), stop_input, headers, ApiEndpoint::Other).await
+        self.make_machines_request(reqwest::Method::POST, &format!("{}/stop", stop_input.id), stop_input, headers, ApiEndpoint::Other).await.map(ignore_body)
     }
 
     pub async fn restart(&self, restart_input: RestartMachineInput, nonce: Option<String>) -> Result<()> {
@@ -372,7 +753,7 @@ impl Client {
         }
         let restart_query = encode_url_params(&url_params);
 
-        self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/restart{restart_query}"), (), headers, ApiEndpoint::Other).await
+        self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/restart{restart_query}"), (), headers, ApiEndpoint::Other).await.map(ignore_body)
     }
 
     pub async fn get<M: AsMachineId>(&self, machine: &M) -> Result<entities::machine::Machine> {
@@ -381,32 +762,46 @@ impl Client {
         self.make_machines_request(reqwest::Method::GET, machine_id, (), Vec::new(), ApiEndpoint::Other).await
     }
 
+    /// Fetches several machines, with at most [`FlapsSettingsBuilder::max_concurrency`] (or 16) requests in flight.
+    /// The machines are returned in the same order as `machine_ids`.
     pub async fn get_many<M: AsMachineId>(&self, machine_ids: &[M]) -> Result<Vec<entities::machine::Machine>> {
-        let futures = machine_ids.iter().map(|id| {
-            self.get(id)
-        });
-        futures::future::try_join_all(futures).await
+        use futures::{StreamExt, TryStreamExt};
+
+        futures::stream::iter(machine_ids.iter().map(|id| self.get(id)))
+            .buffered(self.raw.limiter.max_concurrency().unwrap_or(DEFAULT_FAN_OUT))
+            .try_collect()
+            .await
     }
 
-    pub async fn list(&self, state: Option<&str>) -> Result<Vec<entities::machine::Machine>> {
-        self.make_machines_request(
+    /// Lists the app's machines, optionally filtered. See [`ListMachinesFilter`] for which filters
+    /// are applied by the API and which are applied after the response is received.
+    pub async fn list(&self, filter: Option<&ListMachinesFilter>) -> Result<Vec<entities::machine::Machine>> {
+        let mut machs: Vec<entities::machine::Machine> = self.make_machines_request(
             reqwest::Method::GET,
-            &state.map(|s| "?".to_string() + s).unwrap_or_default(),
+            &filter.map(ListMachinesFilter::query_string).unwrap_or_default(),
             (),
             Vec::new(),
             ApiEndpoint::Other
-        ).await
+        ).await?;
+        if let Some(filter) = filter {
+            machs.retain(|m| filter.matches(m));
+        }
+        Ok(machs)
     }
     #[doc(hidden)]
-    pub async fn list_into(&self, vec: &mut Vec<entities::machine::Machine>, state: Option<&str>) -> Result<()> {
+    pub async fn list_into(&self, vec: &mut Vec<entities::machine::Machine>, filter: Option<&ListMachinesFilter>) -> Result<()> {
         self.make_machines_request_into(
             reqwest::Method::GET,
-            &state.map(|s| "?".to_string() + s).unwrap_or_default(),
+            &filter.map(ListMachinesFilter::query_string).unwrap_or_default(),
             (),
             vec,
             Vec::new(),
             ApiEndpoint::Other
-        ).await
+        ).await?;
+        if let Some(filter) = filter {
+            vec.retain(|m| filter.matches(m));
+        }
+        Ok(())
     }
 
     /// returns only non-destroyed machines that aren't in a reserved process group
@@ -428,11 +823,11 @@ impl Client {
 
     /// returns machines that are part of the fly apps platform that are not destroyed, excluding console machines
     pub async fn list_fly_apps_machines(&self) -> Result<FlyAppsMachines> {
-        let n = backoff::ExponentialBackoffBuilder::new()
-            .with_initial_interval(Duration::from_millis(500))
-            .with_max_interval(Duration::from_millis(5000))
-            .with_multiplier(1.5)
-            .build();
+        // The app's machines may not be visible right after it's created, so keep trying for a while.
+        let n = backoff::ExponentialBackoff {
+            max_elapsed_time: Some(Duration::from_secs(15 * 60)),
+            ..self.raw.retry_policy.backoff()
+        };
 
         backoff::future::retry(n, || async {
 
@@ -462,7 +857,9 @@ impl Client {
         }).await
     }
 
+    /// Destroys a machine with `DELETE /machines/{id}`. Without `kill`, the machine has to be stopped first.
     pub async fn destroy(&self, input: RemoveMachineInput, nonce: Option<String>) -> Result<()> {
+        let leased = nonce.is_some();
         let mut headers = Vec::new();
         add_lease_nonce(&mut headers, nonce);
 
@@ -471,13 +868,18 @@ impl Client {
             false => "false",
         };
 
-        self.make_machines_request(reqwest::Method::DELETE, &format!("{}/destroy?kill={kill}", input.id), (), headers, ApiEndpoint::Other).await
+        self.make_machines_request(reqwest::Method::DELETE, &format!("{}?kill={kill}", input.id), (), headers, ApiEndpoint::Other).await.map(ignore_body)?;
+        // The lease goes away with the machine
+        if leased {
+            self.raw.metrics.lease_released();
+        }
+        Ok(())
     }
 
     pub async fn kill<M: AsMachineId>(&self, machine: M) -> Result<()> {
         let machine_id = machine.as_machine_id();
 
-        self.make_machines_request(reqwest::Method::POST, &format!("{}/signal", machine_id), Signal::from(9), Vec::new(), ApiEndpoint::Other).await
+        self.make_machines_request(reqwest::Method::POST, &format!("{}/signal", machine_id), Signal::from(9), Vec::new(), ApiEndpoint::Other).await.map(ignore_body)
     }
 
     pub async fn find_lease<M: AsMachineId>(&self, machine: M) -> Result<Option<MachineLease>> {
@@ -514,7 +916,9 @@ impl Client {
         }
         let lease_query = encode_url_params(&url_params);
 
-        self.make_machines_request(reqwest::Method::POST, &format!("{}/lease{lease_query}", machine_id), (), Vec::new(), ApiEndpoint::Other).await
+        let lease = self.make_machines_request(reqwest::Method::POST, &format!("{}/lease{lease_query}", machine_id), (), Vec::new(), ApiEndpoint::Other).await?;
+        self.raw.metrics.lease_acquired();
+        Ok(lease)
     }
 
     pub async fn refresh_lease<M: AsMachineId>(&self, machine: M, ttl: Option<i32>, nonce: String) -> Result<MachineLease> {
@@ -538,7 +942,9 @@ impl Client {
         let mut headers = Vec::new();
         add_lease_nonce(&mut headers, nonce);
 
-        self.make_machines_request(reqwest::Method::DELETE, &format!("{}/lease", machine_id), (), headers, ApiEndpoint::Other).await
+        self.make_machines_request(reqwest::Method::DELETE, &format!("{}/lease", machine_id), (), headers, ApiEndpoint::Other).await.map(ignore_body)?;
+        self.raw.metrics.lease_released();
+        Ok(())
     }
 
     pub async fn exec<M: AsMachineId>(&self, machine: M, input: MachineExecRequest) -> Result<MachineExecResponse> {
@@ -547,9 +953,185 @@ impl Client {
         self.make_machines_request(reqwest::Method::POST, &format!("{}/exec", machine_id), input, Vec::new(), ApiEndpoint::Other).await
     }
 
+    /// Runs a command on a machine from an argv vector, optionally feeding it stdin.
+    ///
+    /// The Machines API runs the command to completion and returns its output in one response,
+    /// so the whole output is held in memory. There's no TTY and no streaming; for interactive
+    /// sessions or output that doesn't fit in memory, use SSH.
+    pub async fn exec_command<M: AsMachineId>(&self, machine: M, input: MachineExecCommand) -> Result<MachineExecOutput> {
+        let machine_id = machine.as_machine_id();
+
+        self.make_machines_request(reqwest::Method::POST, &format!("{}/exec", machine_id), input, Vec::new(), ApiEndpoint::Other).await
+    }
+
     pub async fn get_processes<M: AsMachineId>(&self, machine: M) -> Result<Vec<crate::entities::ProcessStat>> {
         let machine_id = machine.as_machine_id();
 
         self.make_machines_request(reqwest::Method::GET, &format!("{}/ps", machine_id), (), Vec::new(), ApiEndpoint::Other).await
     }
-}
\ No newline at end of file
+
+    pub async fn get_metadata<M: AsMachineId>(&self, machine: M) -> Result<BTreeMap<String, String>> {
+        let machine_id = machine.as_machine_id();
+
+        self.make_machines_request(reqwest::Method::GET, &format!("{machine_id}/metadata"), (), Vec::new(), ApiEndpoint::Other).await
+    }
+
+    /// Sets a single metadata key, without updating (and restarting) the machine.
+    pub async fn set_metadata<M: AsMachineId>(&self, machine: M, key: &str, value: String, nonce: Option<String>) -> Result<()> {
+        let machine_id = machine.as_machine_id();
+        let key = urlencoding::encode(key);
+
+        let mut headers = Vec::new();
+        add_lease_nonce(&mut headers, nonce);
+
+        self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/metadata/{key}"), SetMetadataRequest { value }, headers, ApiEndpoint::Other).await.map(ignore_body)
+    }
+
+    pub async fn delete_metadata<M: AsMachineId>(&self, machine: M, key: &str, nonce: Option<String>) -> Result<()> {
+        let machine_id = machine.as_machine_id();
+        let key = urlencoding::encode(key);
+
+        let mut headers = Vec::new();
+        add_lease_nonce(&mut headers, nonce);
+
+        self.make_machines_request(reqwest::Method::DELETE, &format!("{machine_id}/metadata/{key}"), (), headers, ApiEndpoint::Other).await.map(ignore_body)
+    }
+
+    /// Stops the proxy from routing new requests to the machine. The machine keeps running.
+    pub async fn cordon<M: AsMachineId>(&self, machine: M, nonce: Option<String>) -> Result<()> {
+        let machine_id = machine.as_machine_id();
+
+        let mut headers = Vec::new();
+        add_lease_nonce(&mut headers, nonce);
+
+        self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/cordon"), (), headers, ApiEndpoint::Other).await.map(ignore_body)
+    }
+
+    /// Undoes [`cordon`](Self::cordon), letting the proxy route requests to the machine again.
+    pub async fn uncordon<M: AsMachineId>(&self, machine: M, nonce: Option<String>) -> Result<()> {
+        let machine_id = machine.as_machine_id();
+
+        let mut headers = Vec::new();
+        add_lease_nonce(&mut headers, nonce);
+
+        self.make_machines_request(reqwest::Method::POST, &format!("{machine_id}/uncordon"), (), headers, ApiEndpoint::Other).await.map(ignore_body)
+    }
+}
+#[test]
+fn test_map_flaps_error() {
+    let map = |status: u16, body: &'static str, headers: http::HeaderMap| {
+        let status = http::StatusCode::from_u16(status).unwrap();
+        map_flaps_error(bytes::Bytes::from_static(body.as_bytes()), Some("req-1".to_string()), status, &headers, ApiEndpoint::Other)
+    };
+
+    let err = map(422, r#"{"error":"invalid config","details":[{"field":"config.guest.cpus","message":"must be at least 1"}]}"#, http::HeaderMap::new());
+    assert!(matches!(&err, FlapsError::Validation{details, ..} if details[0].field == "config.guest.cpus"));
+    assert_eq!(err.status(), Some(http::StatusCode::UNPROCESSABLE_ENTITY));
+    assert_eq!(err.request_id(), Some("req-1"));
+    assert!(!err.is_retryable());
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::RETRY_AFTER, http::HeaderValue::from_static("5"));
+    let err = map(429, r#"{"error":"rate limited"}"#, headers);
+    assert!(matches!(err, FlapsError::RateLimited{retry_after: Some(d), ..} if d == Duration::from_secs(5)));
+    assert!(err.is_retryable());
+
+    assert!(matches!(map(412, r#"{"error":"lease mismatch"}"#, http::HeaderMap::new()), FlapsError::PreconditionFailed(_)));
+    assert!(matches!(map(401, "", http::HeaderMap::new()), FlapsError::Unauthorized(_)));
+    assert!(map(502, "<html>Bad Gateway</html>", http::HeaderMap::new()).is_retryable());
+    // Not retried by the default policy either
+    assert!(!map(500, r#"{"error":"internal error"}"#, http::HeaderMap::new()).is_retryable());
+}
+
+#[test]
+fn test_app_resource_urls() {
+    let client = Client::new(FlapsSettings::builder()
+        .base_url("https://api.machines.dev")
+        .auth_token("token")
+        .app_name("my-app")
+        .build()).unwrap();
+
+    let url = |resource, endpoint| client.app_resource_url(resource, endpoint).unwrap().to_string();
+
+    assert_eq!(url("machines", ""), "https://api.machines.dev/v1/apps/my-app/machines");
+    assert_eq!(url("machines", "?include_deleted=true"), "https://api.machines.dev/v1/apps/my-app/machines?include_deleted=true");
+    assert_eq!(url("machines", "abc123/start"), "https://api.machines.dev/v1/apps/my-app/machines/abc123/start");
+    assert_eq!(url("volumes", "vol_123/snapshots"), "https://api.machines.dev/v1/apps/my-app/volumes/vol_123/snapshots");
+}
+
+/// Method, URL, headers and body of a request sent through a [`CannedTransport`].
+#[cfg(test)]
+type CannedRequest = (http::Method, String, Vec<HeaderPair>, String);
+
+#[cfg(test)]
+struct CannedTransport {
+    response: (u16, &'static str),
+    requests: std::sync::Mutex<Vec<CannedRequest>>,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl Transport for CannedTransport {
+    async fn make_request(&self, _user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> Result<TransportResult> {
+        self.requests.lock().unwrap().push((method, url.to_string(), headers, json));
+        let (status, body) = self.response;
+        Ok(TransportResult::new(http::StatusCode::from_u16(status).unwrap(), http::HeaderMap::new(), bytes::Bytes::from_static(body.as_bytes())))
+    }
+}
+
+/// A client for `my-app` at `https://flaps.test` that answers every request with `status` and `body`.
+/// Prefer [`FakeFlaps`](crate::testing::FakeFlaps) for endpoints it implements.
+#[cfg(test)]
+fn canned_client(status: u16, body: &'static str) -> (Client, Arc<CannedTransport>) {
+    let transport = Arc::new(CannedTransport {
+        response: (status, body),
+        requests: Default::default(),
+    });
+    let client = Client::with_transport(FlapsSettings::builder()
+        .base_url("https://flaps.test")
+        .app_name("my-app")
+        .build(), transport.clone()).unwrap();
+    (client, transport)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_custom_transport() {
+    let (client, transport) = canned_client(200, r#"{"ok":true}"#);
+
+    client.cordon("m1", Some("nonce-1".to_string())).await.unwrap();
+
+    let requests = transport.requests.lock().unwrap();
+    let (method, url, headers, _) = &requests[0];
+    assert_eq!(method, http::Method::POST);
+    assert_eq!(url, "https://flaps.test/v1/apps/my-app/machines/m1/cordon");
+    assert_eq!(headers[0].name(), "fly-machine-lease-nonce");
+    assert_eq!(headers[0].value(), "nonce-1");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_destroy_url() {
+    let (client, transport) = canned_client(200, r#"{"ok":true}"#);
+
+    client.destroy(RemoveMachineInput { id: "m1".to_string(), kill: true }, None).await.unwrap();
+
+    let requests = transport.requests.lock().unwrap();
+    let (method, url, _, _) = &requests[0];
+    assert_eq!(method, http::Method::DELETE);
+    assert_eq!(url, "https://flaps.test/v1/apps/my-app/machines/m1?kill=true");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_unit_responses() {
+    // The API acknowledges these with `{"ok":true}` or an empty body, neither of which is a `()`
+    for body in [r#"{"ok":true}"#, ""] {
+        let (client, _) = canned_client(200, body);
+
+        client.stop(StopMachineInput { id: "m1".to_string(), signal: "SIGINT".to_string(), timeout: Duration::from_secs(5).into() }, None).await.unwrap();
+        client.restart(RestartMachineInput { id: "m1".to_string(), signal: None, timeout: None, force_stop: false }, None).await.unwrap();
+        client.kill("m1").await.unwrap();
+        client.release_lease("m1", None).await.unwrap();
+    }
+}
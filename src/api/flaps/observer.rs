@@ -0,0 +1,190 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
+/// Identifies a request for metrics. `endpoint` is the path with IDs replaced by placeholders,
+/// e.g. `/v1/apps/{app}/machines/{machine_id}/stop`, so that it can be used as a label.
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    pub method: http::Method,
+    pub endpoint: String,
+}
+
+impl RequestInfo {
+    pub(super) fn new(method: &http::Method, url: &url::Url) -> Self {
+        RequestInfo { method: method.clone(), endpoint: endpoint_template(url) }
+    }
+}
+
+/// How a request went, including any retries.
+#[derive(Debug, Clone)]
+pub struct RequestOutcome {
+    /// Status of the last response, if there was one.
+    pub status: Option<http::StatusCode>,
+    /// [`FlapsError::kind`](super::FlapsError::kind) of the error, if the request failed.
+    /// A [`Client::wait`](super::Client::wait) that times out isn't counted as failed, since
+    /// [`Client::wait_for_state`](super::Client::wait_for_state) polls with short waits that are expected to time out.
+    pub error: Option<&'static str>,
+    pub latency: Duration,
+    pub attempts: u32,
+}
+
+/// Receives metrics from a client. Set it with [`FlapsSettingsBuilder::metrics_observer`](super::FlapsSettingsBuilder::metrics_observer).
+///
+/// Every method does nothing by default. They're called inline with requests, so they should be quick.
+pub trait MetricsObserver: Send + Sync {
+    fn request_started(&self, _request: &RequestInfo) {}
+    fn request_finished(&self, _request: &RequestInfo, _outcome: &RequestOutcome) {}
+
+    /// A lease was acquired with [`Client::acquire_lease`](super::Client::acquire_lease) or [`Client::lease`](super::Client::lease).
+    fn lease_acquired(&self) {}
+    /// A lease was released, or its machine was destroyed with the lease's nonce.
+    ///
+    /// Leases that are left to expire aren't reported, so the difference between acquisitions and
+    /// releases isn't the number of leases currently held. Use the lease guard methods for that.
+    fn lease_released(&self) {}
+
+    /// A [`LeaseGuard`](super::LeaseGuard) started holding a lease.
+    ///
+    /// Only guards are tracked: leases taken with [`Client::acquire_lease`](super::Client::acquire_lease)
+    /// are reported to [`lease_acquired`](Self::lease_acquired) alone.
+    fn lease_guard_started(&self) {}
+    /// A lease guard stopped holding its lease: it was released, its machine was destroyed, it was
+    /// dropped, or the lease expired because it couldn't be refreshed. Called once per guard.
+    fn lease_guard_finished(&self) {}
+
+    /// A [`Client::wait`](super::Client::wait) started.
+    fn wait_started(&self) {}
+    /// A wait finished, failed or was cancelled.
+    fn wait_finished(&self) {}
+}
+
+/// The default observer, which ignores everything.
+pub struct NoopMetricsObserver;
+
+impl MetricsObserver for NoopMetricsObserver {}
+
+/// Reports [`wait_finished`](MetricsObserver::wait_finished) when dropped, so that cancelled waits are counted too.
+pub(super) struct WaitGuard(Arc<dyn MetricsObserver>);
+
+impl WaitGuard {
+    pub(super) fn new(observer: &Arc<dyn MetricsObserver>) -> Self {
+        observer.wait_started();
+        WaitGuard(observer.clone())
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        self.0.wait_finished();
+    }
+}
+
+/// Reports [`lease_guard_finished`](MetricsObserver::lease_guard_finished) the first time it is
+/// finished or when dropped. Shared between a lease guard and its refresh task, so that whichever
+/// of them notices the lease going away first reports it.
+pub(super) struct ActiveLease {
+    observer: Arc<dyn MetricsObserver>,
+    finished: AtomicBool,
+}
+
+impl ActiveLease {
+    pub(super) fn new(observer: &Arc<dyn MetricsObserver>) -> Self {
+        observer.lease_guard_started();
+        ActiveLease { observer: observer.clone(), finished: AtomicBool::new(false) }
+    }
+
+    pub(super) fn finish(&self) {
+        if !self.finished.swap(true, Ordering::AcqRel) {
+            self.observer.lease_guard_finished();
+        }
+    }
+}
+
+impl Drop for ActiveLease {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn endpoint_template(url: &url::Url) -> String {
+    let mut out = String::new();
+    let mut previous = "";
+    for segment in url.path_segments().into_iter().flatten().filter(|s| !s.is_empty()) {
+        out.push('/');
+        out.push_str(match previous {
+            "apps" => "{app}",
+            "machines" => "{machine_id}",
+            "volumes" => "{volume_id}",
+            "metadata" => "{key}",
+            _ => segment,
+        });
+        previous = segment;
+    }
+    out
+}
+
+/// Reports to the [`metrics`](::metrics) crate's global recorder, e.g. a Prometheus exporter. Enabled by the `metrics` feature.
+///
+/// | Metric | Type | Labels |
+/// |---|---|---|
+/// | `flyio_api_requests_total` | counter | `method`, `endpoint`, `status` |
+/// | `flyio_api_request_errors_total` | counter | `method`, `endpoint`, `kind` |
+/// | `flyio_api_request_duration_seconds` | histogram | `method`, `endpoint` |
+/// | `flyio_api_request_retries_total` | counter | `method`, `endpoint` |
+/// | `flyio_api_leases_acquired_total` | counter | |
+/// | `flyio_api_leases_released_total` | counter | |
+/// | `flyio_api_leases_active` | gauge | |
+/// | `flyio_api_waits_in_flight` | gauge | |
+#[cfg(feature = "metrics")]
+pub struct MetricsCrateObserver;
+
+#[cfg(feature = "metrics")]
+impl MetricsObserver for MetricsCrateObserver {
+    fn request_finished(&self, request: &RequestInfo, outcome: &RequestOutcome) {
+        let method = request.method.to_string();
+        let endpoint = request.endpoint.clone();
+        let status = outcome.status.map(|s| s.as_u16().to_string()).unwrap_or_else(|| "none".to_string());
+
+        ::metrics::counter!("flyio_api_requests_total", "method" => method.clone(), "endpoint" => endpoint.clone(), "status" => status).increment(1);
+        ::metrics::histogram!("flyio_api_request_duration_seconds", "method" => method.clone(), "endpoint" => endpoint.clone()).record(outcome.latency.as_secs_f64());
+        if outcome.attempts > 1 {
+            ::metrics::counter!("flyio_api_request_retries_total", "method" => method.clone(), "endpoint" => endpoint.clone()).increment(u64::from(outcome.attempts - 1));
+        }
+        if let Some(kind) = outcome.error {
+            ::metrics::counter!("flyio_api_request_errors_total", "method" => method, "endpoint" => endpoint, "kind" => kind).increment(1);
+        }
+    }
+
+    fn lease_acquired(&self) {
+        ::metrics::counter!("flyio_api_leases_acquired_total").increment(1);
+    }
+
+    fn lease_released(&self) {
+        ::metrics::counter!("flyio_api_leases_released_total").increment(1);
+    }
+
+    fn lease_guard_started(&self) {
+        ::metrics::gauge!("flyio_api_leases_active").increment(1.0);
+    }
+
+    fn lease_guard_finished(&self) {
+        ::metrics::gauge!("flyio_api_leases_active").decrement(1.0);
+    }
+
+    fn wait_started(&self) {
+        ::metrics::gauge!("flyio_api_waits_in_flight").increment(1.0);
+    }
+
+    fn wait_finished(&self) {
+        ::metrics::gauge!("flyio_api_waits_in_flight").decrement(1.0);
+    }
+}
+
+#[test]
+fn test_endpoint_template() {
+    let template = |url: &str| endpoint_template(&url::Url::parse(url).unwrap());
+
+    assert_eq!(template("https://api.machines.dev/v1/apps/my-app/machines/3d8d9e1c/stop?signal=SIGINT"), "/v1/apps/{app}/machines/{machine_id}/stop");
+    assert_eq!(template("https://api.machines.dev/v1/apps/my-app/machines/3d8d9e1c/metadata/role"), "/v1/apps/{app}/machines/{machine_id}/metadata/{key}");
+    assert_eq!(template("https://api.machines.dev/v1/apps/my-app/volumes/vol_123/extend"), "/v1/apps/{app}/volumes/{volume_id}/extend");
+    assert_eq!(template("https://api.machines.dev/v1/apps?org_slug=personal"), "/v1/apps");
+}
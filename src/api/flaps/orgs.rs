@@ -0,0 +1,130 @@
+use super::{OrgClient, Result, AsAppName};
+use crate::entities::org::{Organization, OrganizationMember};
+
+const ORGANIZATION_FIELDS: &str = "id slug name type billingStatus members { edges { role node { id name email } } }";
+
+// GraphQL shapes, converted into the flatter entities in `crate::entities::org`.
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlOrganization {
+    id: String,
+    slug: String,
+    name: String,
+    #[serde(rename = "type")]
+    type_: String,
+    billing_status: Option<String>,
+    members: Option<GqlConnection<GqlMemberEdge>>,
+}
+
+#[derive(serde::Deserialize)]
+struct GqlConnection<T> {
+    edges: Vec<T>,
+}
+
+#[derive(serde::Deserialize)]
+struct GqlNodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(serde::Deserialize)]
+struct GqlMemberEdge {
+    role: String,
+    node: GqlUser,
+}
+
+#[derive(serde::Deserialize)]
+struct GqlUser {
+    id: String,
+    name: String,
+    email: String,
+}
+
+impl From<GqlOrganization> for Organization {
+    fn from(org: GqlOrganization) -> Self {
+        Organization {
+            id: org.id,
+            slug: org.slug,
+            name: org.name,
+            type_: org.type_,
+            billing_status: org.billing_status,
+            members: org.members.map(|m| m.edges).unwrap_or_default().into_iter().map(|edge| OrganizationMember {
+                id: edge.node.id,
+                name: edge.node.name,
+                email: edge.node.email,
+                role: edge.role,
+                extra: Default::default(),
+            }).collect(),
+            extra: Default::default(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct OrganizationData<T> {
+    organization: T,
+}
+
+#[derive(serde::Deserialize)]
+struct OrganizationsData {
+    organizations: GqlNodes<GqlOrganization>,
+}
+
+#[derive(serde::Deserialize)]
+struct OrganizationId {
+    id: String,
+}
+
+impl OrgClient {
+
+    /// Fetches the organization this client is scoped to.
+    pub async fn organization(&self) -> Result<Organization> {
+        let query = format!("query($slug: String!) {{ organization(slug: $slug) {{ {ORGANIZATION_FIELDS} }} }}");
+
+        let res: OrganizationData<GqlOrganization> = self.raw.graphql(&query, serde_json::json!({ "slug": self.org_slug })).await?;
+        Ok(res.organization.into())
+    }
+
+    /// Lists every organization the token has access to.
+    pub async fn list_organizations(&self) -> Result<Vec<Organization>> {
+        let query = format!("query {{ organizations {{ nodes {{ {ORGANIZATION_FIELDS} }} }} }}");
+
+        let res: OrganizationsData = self.raw.graphql(&query, serde_json::json!({})).await?;
+        Ok(res.organizations.nodes.into_iter().map(Organization::from).collect())
+    }
+
+    /// Resolves an organization slug to its ID.
+    /// Returns [`FlapsError::NotFound`](super::FlapsError::NotFound) if there's no such organization.
+    pub async fn resolve_org_id(&self, slug: &str) -> Result<String> {
+        let query = "query($slug: String!) { organization(slug: $slug) { id } }";
+
+        let res: OrganizationData<OrganizationId> = self.raw.graphql(query, serde_json::json!({ "slug": slug })).await?;
+        Ok(res.organization.id)
+    }
+
+    /// Checks whether `app` belongs to this client's organization.
+    pub async fn owns_app<A: AsAppName>(&self, app: &A) -> Result<bool> {
+        let app = self.get_app(app).await?;
+
+        Ok(app.organization.is_some_and(|org| org.slug == self.org_slug))
+    }
+}
+
+#[test]
+fn test_organization_from_graphql() {
+    let json = r#"{
+        "organization": {
+            "id": "O1abc", "slug": "acme", "name": "Acme Inc", "type": "SHARED", "billingStatus": "CURRENT",
+            "members": { "edges": [ { "role": "ADMIN", "node": { "id": "U1", "name": "Jo", "email": "jo@acme.test" } } ] }
+        }
+    }"#;
+    let res: OrganizationData<GqlOrganization> = serde_json::from_str(json).unwrap();
+    let org = Organization::from(res.organization);
+
+    assert_eq!(org.slug, "acme");
+    assert!(!org.is_personal());
+    assert_eq!(org.billing_status.as_deref(), Some("CURRENT"));
+    assert_eq!(org.members.len(), 1);
+    assert_eq!(org.members[0].role, "ADMIN");
+    assert_eq!(org.members[0].email, "jo@acme.test");
+}
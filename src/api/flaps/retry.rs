@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use super::TransportResult;
+
+/// The statuses [`RetryPolicy::default`] retries, which are also what [`FlapsError::is_retryable`](super::FlapsError::is_retryable) considers transient.
+pub(super) const DEFAULT_RETRYABLE_STATUSES: [http::StatusCode; 4] = [
+    http::StatusCode::TOO_MANY_REQUESTS,
+    http::StatusCode::BAD_GATEWAY,
+    http::StatusCode::SERVICE_UNAVAILABLE,
+    http::StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// Controls how requests that fail transiently are retried, see [`FlapsSettingsBuilder::retry_policy`](super::FlapsSettingsBuilder::retry_policy).
+///
+/// A request is retried when it fails with one of `retryable_statuses` or, if `retry_transport_errors`
+/// is set, a connection error, until `max_attempts` requests have been made. Requests with a method that isn't
+/// in `idempotent_methods` are only retried when the server can't have acted on them: on a `429 Too Many Requests`,
+/// or when the connection couldn't be established in the first place.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    pub initial_interval: Duration,
+    pub max_interval: Duration,
+    pub multiplier: f64,
+    /// Each delay is randomized by up to this fraction, in both directions.
+    pub randomization_factor: f64,
+    pub retryable_statuses: Vec<http::StatusCode>,
+    /// Whether to retry when the request couldn't be sent or the response couldn't be read.
+    pub retry_transport_errors: bool,
+    /// Wait as long as the server's `Retry-After` header asks, instead of the backoff delay.
+    pub respect_retry_after: bool,
+    /// Give up instead of waiting if `Retry-After` asks for longer than this.
+    pub max_retry_after: Duration,
+    /// Methods that are safe to repeat. Add `POST` at your own risk.
+    pub idempotent_methods: Vec<http::Method>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_interval: Duration::from_millis(500),
+            max_interval: Duration::from_millis(5000),
+            multiplier: 1.5,
+            randomization_factor: 0.5,
+            retryable_statuses: DEFAULT_RETRYABLE_STATUSES.to_vec(),
+            retry_transport_errors: true,
+            respect_retry_after: true,
+            max_retry_after: Duration::from_secs(60),
+            idempotent_methods: vec![
+                http::Method::GET,
+                http::Method::HEAD,
+                http::Method::OPTIONS,
+                http::Method::PUT,
+                http::Method::DELETE,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries.
+    pub fn none() -> Self {
+        RetryPolicy { max_attempts: 1, ..Default::default() }
+    }
+
+    /// The backoff curve described by this policy, without a time limit.
+    pub fn backoff(&self) -> backoff::ExponentialBackoff {
+        backoff::ExponentialBackoffBuilder::new()
+            .with_initial_interval(self.initial_interval)
+            .with_max_interval(self.max_interval)
+            .with_multiplier(self.multiplier)
+            .with_randomization_factor(self.randomization_factor)
+            .with_max_elapsed_time(None)
+            .build()
+    }
+
+    fn is_idempotent(&self, method: &http::Method) -> bool {
+        self.idempotent_methods.contains(method)
+    }
+
+    /// How long to wait before retrying a request that got `result` on its `attempt`th try (starting at 1),
+    /// or `None` if it shouldn't be retried.
+    pub(super) fn retry_delay(
+        &self,
+        attempt: u32,
+        method: &http::Method,
+        result: &super::Result<TransportResult>,
+        backoff: &mut backoff::ExponentialBackoff,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        match result {
+            Ok(res) if res.status_code.as_u16() > 299 => {
+                let status = res.status_code;
+                if !self.retryable_statuses.contains(&status) {
+                    return None;
+                }
+                // A 429 means the request was turned away before anything happened.
+                if status != http::StatusCode::TOO_MANY_REQUESTS && !self.is_idempotent(method) {
+                    return None;
+                }
+                match self.respect_retry_after.then(|| retry_after(&res.headers)).flatten() {
+                    Some(delay) if delay > self.max_retry_after => None,
+                    Some(delay) => Some(delay),
+                    None => backoff::backoff::Backoff::next_backoff(backoff),
+                }
+            },
+            Ok(_) => None,
+            Err(e) => {
+                if !self.retry_transport_errors {
+                    return None;
+                }
+                let retry = e.is_connection_error() || (e.is_interrupted() && self.is_idempotent(method));
+                if retry {
+                    backoff::backoff::Backoff::next_backoff(backoff)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Parses a `Retry-After` header, given either in seconds or as an HTTP date.
+pub(super) fn retry_after(headers: &http::HeaderMap) -> Option<Duration> {
+    let value = headers.get(http::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = RetryPolicy { randomization_factor: 0.0, ..Default::default() };
+    let mut backoff = policy.backoff();
+    let response = |status: u16, retry_after: Option<&str>| {
+        let mut headers = http::HeaderMap::new();
+        if let Some(retry_after) = retry_after {
+            headers.insert(http::header::RETRY_AFTER, retry_after.parse().unwrap());
+        }
+        Ok(TransportResult::new(http::StatusCode::from_u16(status).unwrap(), headers, bytes::Bytes::new()))
+    };
+
+    assert_eq!(policy.retry_delay(1, &http::Method::GET, &response(503, None), &mut backoff), Some(Duration::from_millis(500)));
+    assert_eq!(policy.retry_delay(2, &http::Method::GET, &response(503, None), &mut backoff), Some(Duration::from_millis(750)));
+    assert_eq!(policy.retry_delay(3, &http::Method::GET, &response(503, None), &mut backoff), None);
+
+    // Non-idempotent requests are only retried when they were rejected outright
+    assert_eq!(policy.retry_delay(1, &http::Method::POST, &response(503, None), &mut backoff), None);
+    assert_eq!(policy.retry_delay(1, &http::Method::POST, &response(429, Some("7")), &mut backoff), Some(Duration::from_secs(7)));
+    assert_eq!(policy.retry_delay(1, &http::Method::GET, &response(429, Some("3600")), &mut backoff), None);
+
+    assert_eq!(policy.retry_delay(1, &http::Method::GET, &response(404, None), &mut backoff), None);
+    assert_eq!(policy.retry_delay(1, &http::Method::GET, &response(200, None), &mut backoff), None);
+    assert_eq!(retry_after(&{
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        headers
+    }), Some(Duration::ZERO));
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use super::{Client, Result, ApiEndpoint, LaunchMachineInput, ListSecretsResponse, UpdateSecretsRequest, UpdateSecretsResponse, SecretDeployment, SecretsUpdate, DEFAULT_FAN_OUT};
+use crate::entities::{machine::{Machine, State}, secret::{Secret, SecretValue}};
+
+impl Client {
+
+    /// Lists the app's secrets. Values are not included, only names and digests.
+    pub async fn list_secrets(&self) -> Result<Vec<Secret>> {
+        let res: ListSecretsResponse = self.raw.make_request(reqwest::Method::GET, self.app_resource_url("secrets", "")?, (), Vec::new(), ApiEndpoint::Other).await?;
+        Ok(res.secrets)
+    }
+
+    /// Sets (`Some`) or unsets (`None`) secrets in a single batch, without touching any machines.
+    /// Running machines keep their current secrets until they're updated, e.g. by [`deploy_secrets`](Self::deploy_secrets).
+    pub async fn stage_secrets(&self, changes: BTreeMap<String, Option<SecretValue>>) -> Result<UpdateSecretsResponse> {
+        let req = UpdateSecretsRequest { values: changes };
+
+        self.raw.make_request(reqwest::Method::POST, self.app_resource_url("secrets", "")?, req, Vec::new(), ApiEndpoint::Other).await
+    }
+
+    /// Sets secrets in a single batch.
+    ///
+    /// With `deploy`, the change is then rolled out with [`deploy_secrets`](Self::deploy_secrets),
+    /// **which restarts every started machine in the app**. Without it, this is the same as
+    /// [`stage_secrets`](Self::stage_secrets).
+    pub async fn set_secrets(&self, secrets: BTreeMap<String, SecretValue>, deploy: bool) -> Result<SecretsUpdate> {
+        let changes = secrets.into_iter().map(|(k, v)| (k, Some(v))).collect();
+        self.update_secrets(changes, deploy).await
+    }
+
+    /// Unsets secrets in a single batch.
+    ///
+    /// With `deploy`, the change is then rolled out with [`deploy_secrets`](Self::deploy_secrets),
+    /// **which restarts every started machine in the app**.
+    pub async fn unset_secrets<S: AsRef<str>>(&self, names: &[S], deploy: bool) -> Result<SecretsUpdate> {
+        let changes = names.iter().map(|n| (n.as_ref().to_string(), None)).collect();
+        self.update_secrets(changes, deploy).await
+    }
+
+    async fn update_secrets(&self, changes: BTreeMap<String, Option<SecretValue>>, deploy: bool) -> Result<SecretsUpdate> {
+        let secrets = self.stage_secrets(changes).await?;
+        let deployments = match deploy {
+            true => self.deploy_secrets(secrets.version).await?,
+            false => Vec::new(),
+        };
+        Ok(SecretsUpdate { secrets, deployments })
+    }
+
+    /// Updates every active machine in place so that it picks up staged secrets.
+    /// `min_secrets_version` should be the version returned by [`stage_secrets`](Self::stage_secrets),
+    /// so that machines don't boot with an older, cached copy of the secrets.
+    ///
+    /// Started machines are restarted; stopped and suspended machines are updated without being started.
+    /// Each machine is leased while it's updated, and machines are updated concurrently, with at most
+    /// [`FlapsSettingsBuilder::max_concurrency`](super::FlapsSettingsBuilder::max_concurrency) (or 16) at a time.
+    /// A machine that fails, e.g. because someone else holds its lease, doesn't stop the others:
+    /// the result for each machine is returned, in the order the machines were listed.
+    pub async fn deploy_secrets(&self, min_secrets_version: Option<u64>) -> Result<Vec<SecretDeployment>> {
+        use futures::StreamExt;
+
+        let machines = self.list_active().await?;
+
+        let deployments = machines.into_iter().map(|machine| async move {
+            let machine_id = machine.id.clone();
+            let result = self.deploy_secrets_to(machine, min_secrets_version).await;
+            SecretDeployment { machine_id, result }
+        });
+        Ok(futures::stream::iter(deployments)
+            .buffered(self.raw.limiter.max_concurrency().unwrap_or(DEFAULT_FAN_OUT))
+            .collect()
+            .await)
+    }
+
+    async fn deploy_secrets_to(&self, machine: Machine, min_secrets_version: Option<u64>) -> Result<Machine> {
+        let lease = self.lease(&machine.id, None).await?;
+        let input = LaunchMachineInput {
+            name: Some(machine.name),
+            region: Some(machine.region),
+            config: machine.config,
+            // Don't start machines that weren't running
+            skip_launch: !matches!(machine.state, State::Started | State::Starting),
+            min_secrets_version,
+            ..Default::default()
+        };
+        let res = lease.update(input).await;
+        // The update went through or it didn't; failing to release the lease early doesn't change that.
+        let _ = lease.release().await;
+        res
+    }
+}
@@ -0,0 +1,198 @@
+use std::{sync::Arc, time::Duration};
+
+use super::{MetricsObserver, RateLimit, RetryPolicy};
+
+/// Settings for a [`Client`](super::Client) or [`OrgClient`](super::OrgClient).
+///
+/// Create them with [`FlapsSettings::builder`], or [`FlapsSettings::from_env`] to pick them up from the
+/// environment of a Fly machine or a flyctl session.
+#[derive(Clone, Default)]
+pub struct FlapsSettings {
+    pub(crate) base_url: Option<String>,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) auth_token: String,
+    pub(crate) app_name: Option<String>,
+    /// Used for the operations that the Machines API doesn't cover, such as listing organizations.
+    pub(crate) graphql_url: Option<String>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) proxy: Option<reqwest::Proxy>,
+    pub(crate) default_headers: http::HeaderMap,
+    pub(crate) http_client: Option<reqwest::Client>,
+    pub(crate) metrics_observer: Option<Arc<dyn MetricsObserver>>,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) max_concurrency: Option<usize>,
+    pub(crate) validate_configs: bool,
+    #[cfg(feature = "unix-socket")]
+    pub(crate) socket_path: Option<std::path::PathBuf>,
+}
+
+impl FlapsSettings {
+    pub fn builder() -> FlapsSettingsBuilder {
+        FlapsSettingsBuilder::default()
+    }
+
+    /// Settings taken from `FLY_API_TOKEN`, `FLY_APP_NAME` and `FLY_FLAPS_BASE_URL`.
+    /// See [`FlapsSettingsBuilder::from_env`].
+    pub fn from_env() -> Self {
+        FlapsSettingsBuilder::from_env().build()
+    }
+}
+
+/// Builds [`FlapsSettings`]. Anything left unset falls back to the same defaults
+/// [`Client::new`](super::Client::new) uses, including reading the app name and base URL from the environment.
+#[derive(Clone, Default)]
+pub struct FlapsSettingsBuilder {
+    settings: FlapsSettings,
+}
+
+impl FlapsSettingsBuilder {
+    /// A builder with the auth token, app name and base URL taken from `FLY_API_TOKEN`, `FLY_APP_NAME`
+    /// and `FLY_FLAPS_BASE_URL`. Variables that are unset or empty are left unset.
+    pub fn from_env() -> Self {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    /// Like [`from_env`](Self::from_env), but looks variables up with `get` instead of in the process environment.
+    pub fn from_env_with(get: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |name| get(name).filter(|v| !v.is_empty());
+        let mut builder = Self::default();
+        if let Some(token) = get("FLY_API_TOKEN") {
+            builder = builder.auth_token(token);
+        }
+        if let Some(app_name) = get("FLY_APP_NAME") {
+            builder = builder.app_name(app_name);
+        }
+        if let Some(base_url) = get("FLY_FLAPS_BASE_URL") {
+            builder = builder.base_url(base_url);
+        }
+        builder
+    }
+
+    /// Defaults to `https://api.machines.dev`, or the internal endpoint when running on Fly.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.settings.base_url = Some(base_url.into());
+        self
+    }
+
+    pub fn graphql_url(mut self, graphql_url: impl Into<String>) -> Self {
+        self.settings.graphql_url = Some(graphql_url.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.settings.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// A bare token, which is sent as a `Bearer` token, or a macaroon beginning with `FlyV1 `.
+    pub fn auth_token(mut self, auth_token: impl Into<String>) -> Self {
+        self.settings.auth_token = auth_token.into();
+        self
+    }
+
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.settings.app_name = Some(app_name.into());
+        self
+    }
+
+    /// Replaces the default [`RetryPolicy`]. Use [`RetryPolicy::none`] to disable retries.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.settings.retry_policy = Some(policy);
+        self
+    }
+
+    /// Limits each attempt of a request, from connecting until the response body has been read.
+    /// Keep it above the timeout passed to [`Client::wait`](super::Client::wait).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.settings.timeout = Some(timeout);
+        self
+    }
+
+    /// Only applies to the `reqwest::Client` built by the crate, not to one passed to [`http_client`](Self::http_client).
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Only applies to the `reqwest::Client` built by the crate, not to one passed to [`http_client`](Self::http_client).
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.settings.proxy = Some(proxy);
+        self
+    }
+
+    /// Adds a header to every request. It replaces the crate's own header of the same name, e.g. `User-Agent`.
+    pub fn default_header(mut self, name: http::header::HeaderName, value: http::HeaderValue) -> Self {
+        self.settings.default_headers.insert(name, value);
+        self
+    }
+
+    /// Sends requests with this client instead of building one. Setting a proxy or connect timeout
+    /// as well is an error, since they can't be applied to an existing client.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.settings.http_client = Some(client);
+        self
+    }
+
+    /// Receives request, lease and wait metrics. By default they're discarded.
+    pub fn metrics_observer(mut self, observer: Arc<dyn MetricsObserver>) -> Self {
+        self.settings.metrics_observer = Some(observer);
+        self
+    }
+
+    /// Limits how fast requests are sent, across every clone of the client. Retries count too.
+    /// Fly.io rate-limits per app and per token, so share one client where possible.
+    pub fn rate_limit(mut self, requests_per_second: f64, burst: u32) -> Self {
+        self.settings.rate_limit = Some(RateLimit { requests_per_second, burst });
+        self
+    }
+
+    /// Limits how many requests are in flight at once, across every clone of the client.
+    /// Long-running requests such as [`Client::wait`](super::Client::wait) hold a slot for their whole duration.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.settings.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Runs [`Config::validate`](crate::entities::machine::Config::validate) before
+    /// [`Client::launch`](super::Client::launch) and [`Client::update`](super::Client::update),
+    /// failing with [`FlapsError::InvalidConfig`](super::FlapsError::InvalidConfig) instead of sending an invalid config.
+    pub fn validate_configs(mut self, validate: bool) -> Self {
+        self.settings.validate_configs = validate;
+        self
+    }
+
+    /// The socket used by [`Client::new_unix_socket`](super::Client::new_unix_socket).
+    /// Defaults to [`DEFAULT_SOCKET_PATH`](super::DEFAULT_SOCKET_PATH).
+    #[cfg(feature = "unix-socket")]
+    pub fn socket_path(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.settings.socket_path = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> FlapsSettings {
+        self.settings
+    }
+}
+
+#[test]
+fn test_from_env() {
+    use std::collections::HashMap;
+    use super::{Client, FlapsClientCreationError};
+
+    let mut env = HashMap::from([
+        ("FLY_API_TOKEN", ""),
+        ("FLY_APP_NAME", "env-app"),
+        ("FLY_FLAPS_BASE_URL", "http://flaps.test:4280"),
+    ]);
+    let from_env = |env: &HashMap<&str, &str>| FlapsSettingsBuilder::from_env_with(|name| env.get(name).map(|v| v.to_string())).build();
+
+    let missing_token = Client::new(from_env(&env));
+    assert!(matches!(missing_token, Err(FlapsClientCreationError::MissingAuthToken)));
+
+    env.insert("FLY_API_TOKEN", "env-token");
+    let client = Client::new(from_env(&env)).unwrap();
+    assert_eq!(client.app_name(), "env-app");
+    assert_eq!(client.app_resource_url("machines", "").unwrap().as_str(), "http://flaps.test:4280/v1/apps/env-app/machines");
+}
@@ -0,0 +1,97 @@
+//! `tracing` instrumentation for requests, enabled by the `tracing` feature.
+//! Without it, everything here compiles to nothing.
+//!
+//! Only the method, path and response metadata are recorded. Request headers, which carry the auth token
+//! and lease nonces, and bodies are never recorded.
+
+use std::{future::Future, time::Duration};
+
+use super::{Result, TransportResult};
+
+/// The span around one API call, including any retries.
+pub(super) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+/// Picks the app name and machine ID out of paths like `/v1/apps/{app}/machines/{id}/stop`.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+fn path_ids(url: &url::Url) -> (Option<&str>, Option<&str>) {
+    let segments: Vec<&str> = url.path_segments().map(|s| s.collect()).unwrap_or_default();
+    let after = |name: &str| segments.windows(2).find(|w| w[0] == name).map(|w| w[1]).filter(|s| !s.is_empty());
+    (after("apps"), after("machines"))
+}
+
+impl RequestSpan {
+    #[cfg(feature = "tracing")]
+    pub(super) fn new(method: &http::Method, url: &url::Url) -> Self {
+        let (app, machine_id) = path_ids(url);
+        let span = tracing::info_span!(
+            "flaps_request",
+            app = app,
+            machine_id = machine_id,
+            http.method = %method,
+            endpoint = url.path(),
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+            attempt = tracing::field::Empty,
+            fly_request_id = tracing::field::Empty,
+        );
+        RequestSpan { span }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(super) fn new(_method: &http::Method, _url: &url::Url) -> Self {
+        RequestSpan {}
+    }
+
+    /// Runs `fut` inside the span.
+    pub(super) fn instrument<F: Future>(&self, fut: F) -> impl Future<Output = F::Output> {
+        #[cfg(feature = "tracing")]
+        return tracing::Instrument::instrument(fut, self.span.clone());
+        #[cfg(not(feature = "tracing"))]
+        fut
+    }
+
+    /// Records the outcome of one attempt. The span's fields end up describing the last one.
+    #[cfg(feature = "tracing")]
+    pub(super) fn record_attempt(&self, attempt: u32, result: &Result<TransportResult>, latency: Duration) {
+        let latency_ms = latency.as_millis() as u64;
+        self.span.record("attempt", attempt);
+        self.span.record("latency_ms", latency_ms);
+        match result {
+            Ok(res) => {
+                let status = res.status_code.as_u16();
+                self.span.record("status", status);
+                if let Some(request_id) = &res.request_id {
+                    self.span.record("fly_request_id", request_id.as_str());
+                }
+                tracing::debug!(attempt, status, latency_ms, fly_request_id = res.request_id.as_deref(), "flaps response");
+            },
+            Err(e) => tracing::debug!(attempt, latency_ms, error = %e, "flaps request failed"),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(super) fn record_attempt(&self, _attempt: u32, _result: &Result<TransportResult>, _latency: Duration) {}
+
+    #[cfg(feature = "tracing")]
+    pub(super) fn record_retry(&self, attempt: u32, delay: Duration) {
+        tracing::info!(attempt, delay_ms = delay.as_millis() as u64, "retrying flaps request");
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(super) fn record_retry(&self, _attempt: u32, _delay: Duration) {}
+}
+
+#[test]
+fn test_path_ids() {
+    let url = url::Url::parse("https://api.machines.dev/v1/apps/my-app/machines/3d8d9e1c/stop?signal=SIGINT").unwrap();
+    assert_eq!(path_ids(&url), (Some("my-app"), Some("3d8d9e1c")));
+
+    let url = url::Url::parse("https://api.machines.dev/v1/apps/my-app/machines").unwrap();
+    assert_eq!(path_ids(&url), (Some("my-app"), None));
+
+    let url = url::Url::parse("https://api.fly.io/graphql").unwrap();
+    assert_eq!(path_ids(&url), (None, None));
+}
@@ -1,112 +1,226 @@
 use async_trait::async_trait;
 
-use super::{HeaderPair, unix::UnixSocketConnector};
-
-
-pub struct HttpTransport(pub reqwest::Client, pub String);
 #[cfg(feature = "unix-socket")]
-pub struct UnixSocketTransport(pub hyper::Client<UnixSocketConnector>);
-
-pub enum Transport {
-    Http(HttpTransport),
-    #[cfg(feature = "unix-socket")]
-    UnixSocket(UnixSocketTransport),
-}
-
-#[async_trait]
-impl TransportImpl for Transport {
-    async fn make_request(&self, user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> super::Result<TransportResult> {
-        match self {
-            Transport::Http(t) => {
-                t.make_request(user_agent, method, url, json, headers).await
-            },
-            #[cfg(feature = "unix-socket")]
-            Transport::UnixSocket(t) => {
-                t.make_request(user_agent, method, url, json, headers).await
-            },
-        }
+use super::unix::UnixSocketConnector;
+
+/// An extra request header, such as the lease nonce.
+#[derive(Debug, Clone)]
+pub struct HeaderPair(pub &'static str, pub String);
+impl HeaderPair {
+    pub(super) fn lease_nonce(nonce: String) -> HeaderPair {
+        HeaderPair("fly-machine-lease-nonce", nonce)
     }
-}
-
-impl From<HttpTransport> for Transport {
-    fn from(t: HttpTransport) -> Self {
-        Transport::Http(t)
+    pub fn name(&self) -> &'static str {
+        self.0
     }
-}
-#[cfg(feature = "unix-socket")]
-impl From<UnixSocketTransport> for Transport {
-    fn from(t: UnixSocketTransport) -> Self {
-        Transport::UnixSocket(t)
+    pub fn value(&self) -> &str {
+        &self.1
     }
 }
 
+/// The raw response to a request, before any error mapping or deserialization.
+#[derive(Debug, Clone)]
 pub struct TransportResult {
     pub body: bytes::Bytes,
     pub status_code: http::StatusCode,
+    pub headers: http::HeaderMap,
+    /// The `fly-request-id` response header, if present
     pub request_id: Option<String>,
 }
 
+impl TransportResult {
+    /// Builds a result, filling in `request_id` from the headers.
+    pub fn new(status_code: http::StatusCode, headers: http::HeaderMap, body: bytes::Bytes) -> Self {
+        let request_id = headers.get("fly-request-id").and_then(|v| v.to_str().ok().map(str::to_string));
+        TransportResult { body, status_code, headers, request_id }
+    }
+}
+
+fn auth_header(auth_token: &str) -> String {
+    if auth_token.starts_with("FlyV1 ") {
+        auth_token.to_string()
+    } else {
+        format!("Bearer {}", auth_token)
+    }
+}
+
+/// The headers for a request. Default headers replace the built-in ones (e.g. to send a different
+/// `User-Agent`), and the request's own headers replace both.
+fn request_headers(user_agent: &str, auth_header: Option<&str>, default_headers: &http::HeaderMap, headers: Vec<HeaderPair>) -> super::Result<http::HeaderMap> {
+    let mut map = http::HeaderMap::new();
+    map.insert(http::header::USER_AGENT, http::HeaderValue::from_str(user_agent).map_err(http::Error::from)?);
+    map.insert(http::header::CONTENT_TYPE, http::HeaderValue::from_static("application/json"));
+    if let Some(auth_header) = auth_header {
+        map.insert(http::header::AUTHORIZATION, http::HeaderValue::from_str(auth_header).map_err(http::Error::from)?);
+    }
+    map.extend(default_headers.clone());
+    for HeaderPair(name, value) in headers {
+        let name = http::header::HeaderName::from_bytes(name.as_bytes()).map_err(http::Error::from)?;
+        map.insert(name, http::HeaderValue::from_str(&value).map_err(http::Error::from)?);
+    }
+    Ok(map)
+}
+
 // TODO: Use real async fns as soon as they are stable
 
+/// Sends requests on behalf of a [`Client`](super::Client).
+///
+/// Implement this to route requests through something other than the built-in transports,
+/// e.g. an in-process fake, a proxy, or a recording layer, and pass it to
+/// [`Client::with_transport`](super::Client::with_transport).
+/// Transports are responsible for authentication; the client never adds an `Authorization` header itself.
+///
+/// Non-2xx responses should be returned as `Ok`; the client maps them to [`FlapsError`](super::FlapsError)s.
 #[async_trait]
-pub(crate) trait TransportImpl {
+pub trait Transport: Send + Sync {
     async fn make_request(&self, user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> super::Result<TransportResult>;
 }
 
+/// Sends requests over HTTP(S) with [`reqwest`].
+pub struct HttpTransport {
+    client: reqwest::Client,
+    auth_header: String,
+    default_headers: http::HeaderMap,
+    timeout: Option<std::time::Duration>,
+}
+
+impl HttpTransport {
+    /// `auth_token` may be a bare token, which is sent as a `Bearer` token, or begin with `FlyV1 `.
+    pub fn new(client: reqwest::Client, auth_token: &str) -> Self {
+        let auth_header = auth_header(auth_token);
+        HttpTransport { client, auth_header, default_headers: http::HeaderMap::new(), timeout: None }
+    }
+
+    /// Headers to send with every request. They replace the transport's own headers of the same name.
+    pub fn with_default_headers(mut self, headers: http::HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Limits each request, from connecting until the response body has been read.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
 #[async_trait]
-impl TransportImpl for HttpTransport {
+impl Transport for HttpTransport {
     async fn make_request(&self, user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> super::Result<TransportResult> {
-        let mut builder = self.0
+        let headers = request_headers(user_agent, Some(&self.auth_header), &self.default_headers, headers)?;
+        let mut builder = self.client
             .request(method, url)
-            .body(json)
-            .header("User-Agent", user_agent)
-            .header("Content-Type", "application/json")
-            .header("Authorization", self.1.as_str());
-
-        for HeaderPair(name, value) in headers {
-            builder = builder.header(name, value);
+            .headers(headers)
+            .body(json);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
         }
 
         let response = builder.send()
             .await?;
 
         let status = response.status();
-        let fly_request_id = response.headers().get("fly-request-id").and_then(|v| v.to_str().ok().map(str::to_string));
+        let headers = response.headers().clone();
         let resp_bytes = response.bytes().await?;
 
-        Ok(TransportResult {
-            body: resp_bytes,
-            status_code: status,
-            request_id: fly_request_id,
-        })
+        Ok(TransportResult::new(status, headers, resp_bytes))
+    }
+}
+
+/// Sends requests to the Machines API over the unix socket available inside Fly machines.
+///
+/// The socket authenticates requests as the machine's app, so no token is needed by default.
+#[cfg(feature = "unix-socket")]
+pub struct UnixSocketTransport {
+    client: hyper::Client<UnixSocketConnector>,
+    auth_header: Option<String>,
+    default_headers: http::HeaderMap,
+    timeout: Option<std::time::Duration>,
+}
+
+#[cfg(feature = "unix-socket")]
+impl UnixSocketTransport {
+    /// Connects to the socket at [`DEFAULT_SOCKET_PATH`](super::DEFAULT_SOCKET_PATH).
+    pub fn new() -> Self {
+        Self::with_path(super::DEFAULT_SOCKET_PATH)
+    }
+
+    pub fn with_path<P: Into<std::path::PathBuf>>(path: P) -> Self {
+        UnixSocketTransport {
+            client: hyper::Client::builder().build(UnixSocketConnector::new(path)),
+            auth_header: None,
+            default_headers: http::HeaderMap::new(),
+            timeout: None,
+        }
+    }
+
+    /// Sends `auth_token` with every request, for sockets that don't authenticate requests themselves.
+    /// Formatted like [`HttpTransport::new`]'s.
+    pub fn with_auth_token(mut self, auth_token: &str) -> Self {
+        self.auth_header = Some(auth_header(auth_token));
+        self
+    }
+
+    /// Headers to send with every request, like [`HttpTransport::with_default_headers`].
+    pub fn with_default_headers(mut self, headers: http::HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Limits each request, from connecting until the response body has been read.
+    /// Requests that take longer fail with [`FlapsError::Timeout`](super::FlapsError::Timeout).
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+#[cfg(feature = "unix-socket")]
+impl Default for UnixSocketTransport {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(feature = "unix-socket")]
 #[async_trait]
-impl TransportImpl for UnixSocketTransport {
+impl Transport for UnixSocketTransport {
     async fn make_request(&self, user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> super::Result<TransportResult> {
+        let headers = request_headers(user_agent, self.auth_header.as_deref(), &self.default_headers, headers)?;
         let mut builder = hyper::Request::builder()
             .method(method)
-            .uri(url.as_str())
-            .header("User-Agent", user_agent)
-            .header("Content-Type", "application/json");
-
-        for HeaderPair(name, value) in headers {
-            builder = builder.header(name, value);
+            .uri(url.as_str());
+        if let Some(request_headers) = builder.headers_mut() {
+            *request_headers = headers;
         }
-
         let req = builder.body(hyper::Body::from(json))?;
-        let response = self.0.request(req).await?;
 
-        let status = response.status();
-        let fly_request_id = response.headers().get("fly-request-id").and_then(|v| v.to_str().ok().map(str::to_string));
-        let resp_bytes = response.into_body();
+        let send = async {
+            let response = self.client.request(req).await?;
 
-        Ok(TransportResult {
-            body: hyper::body::to_bytes(resp_bytes).await?,
-            status_code: status,
-            request_id: fly_request_id,
-        })
+            let status = response.status();
+            let headers = response.headers().clone();
+            let resp_bytes = hyper::body::to_bytes(response.into_body()).await?;
+
+            Ok(TransportResult::new(status, headers, resp_bytes))
+        };
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, send).await.map_err(|_| super::FlapsError::Timeout)?,
+            None => send.await,
+        }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_request_headers() {
+    let mut defaults = http::HeaderMap::new();
+    defaults.insert(http::header::USER_AGENT, http::HeaderValue::from_static("my-service/1.0"));
+    defaults.insert("x-team", http::HeaderValue::from_static("infra"));
+
+    let headers = request_headers("flyio-api-rs/0.1.0", Some("Bearer token"), &defaults, vec![HeaderPair::lease_nonce("nonce-1".to_string())]).unwrap();
+    let user_agents: Vec<_> = headers.get_all(http::header::USER_AGENT).iter().collect();
+    assert_eq!(user_agents, ["my-service/1.0"]);
+    assert_eq!(headers["x-team"], "infra");
+    assert_eq!(headers[http::header::AUTHORIZATION], "Bearer token");
+    assert_eq!(headers["fly-machine-lease-nonce"], "nonce-1");
+}
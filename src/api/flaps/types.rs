@@ -15,6 +15,34 @@ impl AsMachineId for entities::machine::Machine {
     }
 }
 
+pub trait AsAppName: Sized {
+    fn as_app_name(&self) -> &str;
+}
+impl<T: AsRef<str>> AsAppName for T {
+    fn as_app_name(&self) -> &str {
+        self.as_ref()
+    }
+}
+impl AsAppName for entities::app::App {
+    fn as_app_name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub trait AsVolumeId: Sized {
+    fn as_volume_id(&self) -> &str;
+}
+impl<T: AsRef<str>> AsVolumeId for T {
+    fn as_volume_id(&self) -> &str {
+        self.as_ref()
+    }
+}
+impl AsVolumeId for entities::volume::Volume {
+    fn as_volume_id(&self) -> &str {
+        &self.id
+    }
+}
+
 #[derive(Debug, serde::Serialize, Clone, Default)]
 pub struct LaunchMachineInput {
     pub config: Option<entities::machine::Config>,
@@ -22,6 +50,9 @@ pub struct LaunchMachineInput {
     pub name: Option<String>,
     pub skip_launch: bool,
     pub lease_ttl: Option<i32>,
+    /// Makes the machine wait for secrets at least as new as this version, see [`Client::set_secrets`](super::Client::set_secrets).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_secrets_version: Option<u64>,
 
     // Client side only
     #[serde(skip)]
@@ -75,6 +106,11 @@ impl From<i32> for Signal {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SetMetadataRequest {
+    pub value: String,
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct MachineLease {
     pub status: String,
@@ -101,4 +137,328 @@ pub struct MachineExecResponse {
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
-}
\ No newline at end of file
+}
+
+/// Input for [`Client::exec_command`](super::Client::exec_command).
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct MachineExecCommand {
+    /// The program and its arguments, run without a shell
+    pub command: Vec<String>,
+    /// Fed to the process's stdin. The API carries stdin as a JSON string, so it's text only;
+    /// binary input has to be encoded (e.g. with base64) and decoded by the command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+    /// Which container to run in, for machines with more than one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// Seconds before the process is killed. Uses the server default when `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<i32>,
+}
+
+impl MachineExecCommand {
+    pub fn new<S: Into<String>, I: IntoIterator<Item = S>>(command: I) -> Self {
+        Self {
+            command: command.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Output of [`Client::exec_command`](super::Client::exec_command).
+///
+/// Output is kept as bytes. The API sends it as JSON strings, whose contents are taken as-is
+/// without UTF-8 validation, so binary output survives as long as the server passes it through.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MachineExecOutput {
+    #[serde(default)]
+    pub exit_code: i32,
+    /// Signal that killed the process, or 0
+    #[serde(default)]
+    pub exit_signal: i32,
+    #[serde(default, deserialize_with = "deserialize_str_bytes")]
+    pub stdout: bytes::Bytes,
+    #[serde(default, deserialize_with = "deserialize_str_bytes")]
+    pub stderr: bytes::Bytes,
+}
+
+fn deserialize_str_bytes<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bytes::Bytes, D::Error> {
+    struct OptionVisitor;
+    struct BytesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for OptionVisitor {
+        type Value = bytes::Bytes;
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a string, a byte array or null")
+        }
+        fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(bytes::Bytes::new())
+        }
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(bytes::Bytes::new())
+        }
+        fn visit_some<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+            // serde_json hands over the raw contents of a string here, without checking that they're UTF-8
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = bytes::Bytes;
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a string or a byte array")
+        }
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(bytes::Bytes::copy_from_slice(v.as_bytes()))
+        }
+        fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(bytes::Bytes::copy_from_slice(v))
+        }
+        fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut buf = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element::<u8>()? {
+                buf.push(b);
+            }
+            Ok(buf.into())
+        }
+    }
+
+    deserializer.deserialize_option(OptionVisitor)
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CreateVolumeRequest {
+    pub name: String,
+    pub region: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_gb: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_unique_zone: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_retention: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_backup_enabled: Option<bool>,
+    #[serde(rename = "fstype", skip_serializing_if = "Option::is_none")]
+    pub fs_type: Option<String>,
+    #[serde(rename = "compute", skip_serializing_if = "Option::is_none")]
+    pub compute_requirements: Option<entities::machine::Guest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_image: Option<String>,
+    /// Fork an existing volume
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_volume_id: Option<String>,
+    /// Restore from a snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct UpdateVolumeRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_retention: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_backup_enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExtendVolumeRequest {
+    pub size_gb: i32,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ExtendVolumeResponse {
+    pub volume: entities::volume::Volume,
+    /// Whether the attached machine has to be restarted to see the new size
+    pub needs_restart: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CreateAppRequest {
+    #[serde(rename = "app_name")]
+    pub name: String,
+    pub org_slug: String,
+    /// Name of the private network to place the app in; empty for the org's default network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ListAppsResponse {
+    pub total_apps: i32,
+    pub apps: Vec<entities::app::App>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ListSecretsResponse {
+    pub secrets: Vec<entities::secret::Secret>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UpdateSecretsRequest {
+    /// `None` unsets the secret
+    pub values: std::collections::BTreeMap<String, Option<entities::secret::SecretValue>>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct UpdateSecretsResponse {
+    pub secrets: Vec<entities::secret::Secret>,
+    /// Version of the app's secrets after the update
+    pub version: Option<u64>,
+}
+
+/// Result of [`Client::set_secrets`](super::Client::set_secrets) and [`Client::unset_secrets`](super::Client::unset_secrets).
+#[derive(Debug)]
+pub struct SecretsUpdate {
+    pub secrets: UpdateSecretsResponse,
+    /// One entry per machine the change was rolled out to; empty unless it was deployed.
+    pub deployments: Vec<SecretDeployment>,
+}
+
+/// How rolling out secrets to one machine went, from [`Client::deploy_secrets`](super::Client::deploy_secrets).
+#[derive(Debug)]
+pub struct SecretDeployment {
+    pub machine_id: String,
+    pub result: super::Result<entities::machine::Machine>,
+}
+
+/// Filters for [`Client::list`](super::Client::list).
+///
+/// `include_deleted`, `region`, `state` and `metadata` are sent to the API as query parameters.
+/// Process group and image filters are applied client-side, since the API can't express them
+/// (process groups may be stored under the legacy `process_group` metadata key).
+#[derive(Debug, Clone, Default)]
+pub struct ListMachinesFilter {
+    include_deleted: bool,
+    region: Option<String>,
+    states: Vec<entities::machine::State>,
+    metadata: std::collections::BTreeMap<String, String>,
+    process_group: Option<String>,
+    image: Option<String>,
+}
+
+impl ListMachinesFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Also return destroyed machines
+    pub fn include_deleted(mut self, include_deleted: bool) -> Self {
+        self.include_deleted = include_deleted;
+        self
+    }
+    pub fn region(mut self, region: String) -> Self {
+        self.region = Some(region);
+        self
+    }
+    /// Only return machines in this state. May be called more than once to match any of several states.
+    pub fn state(mut self, state: entities::machine::State) -> Self {
+        self.states.push(state);
+        self
+    }
+    /// Only return machines whose config metadata has `key` set to `value`.
+    pub fn metadata(mut self, key: String, value: String) -> Self {
+        self.metadata.insert(key, value);
+        self
+    }
+    /// Only return machines in a process group, see [`Machine::has_process_group`](entities::machine::Machine::has_process_group).
+    pub fn process_group(mut self, group: String) -> Self {
+        self.process_group = Some(group);
+        self
+    }
+    /// Only return machines running an image, see [`ImageRef::matches_str`](entities::machine::ImageRef::matches_str).
+    pub fn image(mut self, image: String) -> Self {
+        self.image = Some(image);
+        self
+    }
+
+    /// The query string for the API-side filters, including the leading `?`, or empty if there are none.
+    pub fn query_string(&self) -> String {
+        let state_names = self.states.iter().map(|s| s.name()).collect::<Vec<_>>().join(",");
+        let metadata_keys: Vec<_> = self.metadata.keys().map(|k| format!("metadata.{k}")).collect();
+
+        let mut params = Vec::new();
+        if self.include_deleted {
+            params.push(super::UrlParam("include_deleted", "true"));
+        }
+        if let Some(region) = self.region.as_deref() {
+            params.push(super::UrlParam("region", region));
+        }
+        if !state_names.is_empty() {
+            params.push(super::UrlParam("state", &state_names));
+        }
+        for (key, value) in metadata_keys.iter().zip(self.metadata.values()) {
+            params.push(super::UrlParam(key, value));
+        }
+        super::encode_url_params(&params)
+    }
+
+    /// Whether a machine passes the filter. This checks the API-side filters as well (except
+    /// `include_deleted`), so it also works on machines that didn't come from a filtered list.
+    pub fn matches(&self, machine: &entities::machine::Machine) -> bool {
+        if self.region.as_ref().is_some_and(|r| *r != machine.region) {
+            return false;
+        }
+        if !self.states.is_empty() && !self.states.contains(&machine.state) {
+            return false;
+        }
+        if !self.metadata.is_empty() {
+            let metadata = machine.config.as_ref().and_then(|c| c.metadata.as_ref());
+            let all_match = self.metadata.iter().all(|(k, v)| metadata.and_then(|m| m.get(k)) == Some(v));
+            if !all_match {
+                return false;
+            }
+        }
+        if self.process_group.as_ref().is_some_and(|g| !machine.has_process_group(g)) {
+            return false;
+        }
+        if self.image.as_ref().is_some_and(|i| !machine.image_ref.matches_str(i)) {
+            return false;
+        }
+        true
+    }
+}
+
+#[test]
+fn test_list_machines_filter_query() {
+    use entities::machine::State;
+
+    assert_eq!(ListMachinesFilter::new().query_string(), "");
+
+    let filter = ListMachinesFilter::new()
+        .include_deleted(true)
+        .region("ord".to_string())
+        .state(State::Started)
+        .state(State::Stopped)
+        .metadata("fly_process_group".to_string(), "web & api".to_string());
+    assert_eq!(
+        filter.query_string(),
+        "?include_deleted=true&region=ord&state=started%2Cstopped&metadata.fly_process_group=web%20%26%20api",
+    );
+}
+#[test]
+fn test_exec_command_serde() {
+    let mut cmd = MachineExecCommand::new(["psql", "-c", "select 1"]);
+    cmd.stdin = Some("hello".to_string());
+    assert_eq!(serde_json::to_string(&cmd).unwrap(), r#"{"command":["psql","-c","select 1"],"stdin":"hello"}"#);
+
+    // Empty output is omitted by the API
+    let out: MachineExecOutput = serde_json::from_str(r#"{"stdout":"ok\n"}"#).unwrap();
+    assert_eq!(out.exit_code, 0);
+    assert_eq!(&out.stdout[..], b"ok\n");
+    assert!(out.stderr.is_empty());
+}
+
+#[test]
+fn test_exec_output_bytes() {
+    // e.g. a pg_dump in the custom format
+    let out: MachineExecOutput = serde_json::from_slice(b"{\"exit_code\":0,\"stdout\":\"PGDMP\xff\xfe\\n\",\"stderr\":null}").unwrap();
+    assert_eq!(&out.stdout[..], b"PGDMP\xff\xfe\n");
+    assert!(out.stderr.is_empty());
+
+    let out: MachineExecOutput = serde_json::from_str(r#"{"stdout":[0,159,146,150]}"#).unwrap();
+    assert_eq!(&out.stdout[..], &[0, 159, 146, 150]);
+}
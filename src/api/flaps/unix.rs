@@ -1,4 +1,4 @@
-use std::{path::Path, pin::Pin, task::{Context, Poll}};
+use std::{path::{Path, PathBuf}, pin::Pin, sync::Arc, task::{Context, Poll}};
 
 use hyper::{Uri, service::Service};
 use pin_project::pin_project;
@@ -43,8 +43,20 @@ impl hyper::client::connect::Connection for UnixSocketStream {
     }
 }
 
+/// Where the Machines API socket is mounted inside Fly machines.
+pub const DEFAULT_SOCKET_PATH: &str = "/.fly/api";
+
+/// Connects every request to the same unix socket, whatever the host in its URL.
 #[derive(Debug, Clone)]
-pub struct UnixSocketConnector;
+pub struct UnixSocketConnector {
+    path: Arc<PathBuf>,
+}
+
+impl UnixSocketConnector {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        UnixSocketConnector { path: Arc::new(path.into()) }
+    }
+}
 
 impl Service<Uri> for UnixSocketConnector {
     type Response = UnixSocketStream;
@@ -52,11 +64,139 @@ impl Service<Uri> for UnixSocketConnector {
     type Future = Pin<Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
 
     fn call(&mut self, _req: Uri) -> Self::Future {
+        let path = self.path.clone();
         Box::pin(async move {
-            UnixSocketStream::new("/.fly/api").await
+            UnixSocketStream::new(path.as_path()).await
         })
     }
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
-}
\ No newline at end of file
+}
+/// Accepts one connection on a fresh socket and answers it with `{"ok":true}`, returning the socket's
+/// path and the raw request.
+#[cfg(test)]
+fn serve_once(name: &str) -> (PathBuf, tokio::task::JoinHandle<String>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let path = std::env::temp_dir().join(format!("flyio-api-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+    let handle = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        // Read the head, then as much body as it announces
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(head_len) = text.find("\r\n\r\n") {
+                let content_length = text[..head_len].lines()
+                    .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                if request.len() >= head_len + 4 + content_length {
+                    break;
+                }
+            }
+            if n == 0 {
+                break;
+            }
+        }
+        let body = r#"{"ok":true}"#;
+        let response = format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}", body.len());
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    (path, handle)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_socket_transport() {
+    use super::{Client, FlapsSettings};
+
+    let (path, server) = serve_once("auth");
+    let client = Client::new_unix_socket(FlapsSettings::builder()
+        .socket_path(&path)
+        .auth_token("secret")
+        .app_name("my-app")
+        .build()).unwrap();
+
+    client.cordon("m1", Some("nonce-1".to_string())).await.unwrap();
+    let request = server.await.unwrap().to_ascii_lowercase();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(request.starts_with("post /v1/apps/my-app/machines/m1/cordon http/1.1\r\n"));
+    assert!(request.contains("\r\nauthorization: bearer secret\r\n"));
+    assert!(request.contains("\r\nfly-machine-lease-nonce: nonce-1\r\n"));
+    assert!(request.contains("\r\nuser-agent: flyio-api-rs-unix/"));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_socket_transport_without_token() {
+    use super::{Client, FlapsSettings};
+
+    let (path, server) = serve_once("no-auth");
+    let client = Client::new_unix_socket(FlapsSettings::builder()
+        .socket_path(&path)
+        .app_name("my-app")
+        .build()).unwrap();
+
+    client.uncordon("m1", None).await.unwrap();
+    let request = server.await.unwrap().to_ascii_lowercase();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(request.starts_with("post /v1/apps/my-app/machines/m1/uncordon http/1.1\r\n"));
+    assert!(!request.contains("authorization:"));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_socket_transport_settings() {
+    use super::{Client, FlapsClientCreationError, FlapsSettings, RetryPolicy};
+
+    let (path, server) = serve_once("settings");
+    let client = Client::new_unix_socket(FlapsSettings::builder()
+        .socket_path(&path)
+        .app_name("my-app")
+        .default_header(http::header::USER_AGENT, http::HeaderValue::from_static("my-agent"))
+        .default_header(http::HeaderName::from_static("x-extra"), http::HeaderValue::from_static("1"))
+        .build()).unwrap();
+
+    client.uncordon("m1", None).await.unwrap();
+    let request = server.await.unwrap().to_ascii_lowercase();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(request.contains("\r\nuser-agent: my-agent\r\n"));
+    assert!(request.contains("\r\nx-extra: 1\r\n"));
+
+    // A server that accepts the connection but never answers
+    let path = std::env::temp_dir().join(format!("flyio-api-{}-timeout.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        drop(stream);
+    });
+    let client = Client::new_unix_socket(FlapsSettings::builder()
+        .socket_path(&path)
+        .app_name("my-app")
+        .retry_policy(RetryPolicy::none())
+        .timeout(std::time::Duration::from_millis(100))
+        .build()).unwrap();
+
+    let err = client.uncordon("m1", None).await.unwrap_err();
+    server.abort();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(err.kind(), "timeout");
+
+    let proxied = Client::new_unix_socket(FlapsSettings::builder()
+        .app_name("my-app")
+        .proxy(reqwest::Proxy::all("http://proxy.test").unwrap())
+        .build());
+    assert!(matches!(proxied, Err(FlapsClientCreationError::HttpSettingsOnSocket)));
+}
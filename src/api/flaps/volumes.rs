@@ -0,0 +1,130 @@
+use super::{
+    Client, Result, ApiEndpoint, AsVolumeId, ignore_body,
+    CreateVolumeRequest, UpdateVolumeRequest, ExtendVolumeRequest, ExtendVolumeResponse,
+};
+use crate::entities::volume::{Volume, VolumeSnapshot};
+
+/// Operations on the volumes of the client's app, under `/v1/apps/{app}/volumes`.
+///
+/// Obtained with [`Client::volumes`].
+pub struct VolumesClient<'a>(&'a Client);
+
+impl Client {
+    pub fn volumes(&self) -> VolumesClient<'_> {
+        VolumesClient(self)
+    }
+}
+
+impl VolumesClient<'_> {
+
+    async fn make_volumes_request<
+        Res: serde::de::DeserializeOwned,
+        Req: serde::Serialize,
+    >(
+        &self,
+        method: reqwest::Method,
+        endpoint: &str,
+        data: Req,
+    ) -> Result<Res> {
+        let url = self.0.app_resource_url("volumes", endpoint)?;
+        self.0.raw.make_request(method, url, data, Vec::new(), ApiEndpoint::Other).await
+    }
+
+    pub async fn create(&self, req: CreateVolumeRequest) -> Result<Volume> {
+        self.make_volumes_request(reqwest::Method::POST, "", req).await
+    }
+
+    pub async fn list(&self) -> Result<Vec<Volume>> {
+        self.make_volumes_request(reqwest::Method::GET, "", ()).await
+    }
+
+    pub async fn get<V: AsVolumeId>(&self, volume: &V) -> Result<Volume> {
+        let volume_id = volume.as_volume_id();
+
+        self.make_volumes_request(reqwest::Method::GET, volume_id, ()).await
+    }
+
+    pub async fn update<V: AsVolumeId>(&self, volume: &V, req: UpdateVolumeRequest) -> Result<Volume> {
+        let volume_id = volume.as_volume_id();
+
+        self.make_volumes_request(reqwest::Method::PUT, volume_id, req).await
+    }
+
+    /// Grows a volume to `size_gb`. Volumes can't be shrunk.
+    pub async fn extend<V: AsVolumeId>(&self, volume: &V, size_gb: i32) -> Result<ExtendVolumeResponse> {
+        let volume_id = volume.as_volume_id();
+
+        self.make_volumes_request(reqwest::Method::PUT, &format!("{volume_id}/extend"), ExtendVolumeRequest { size_gb }).await
+    }
+
+    pub async fn delete<V: AsVolumeId>(&self, volume: &V) -> Result<Volume> {
+        let volume_id = volume.as_volume_id();
+
+        self.make_volumes_request(reqwest::Method::DELETE, volume_id, ()).await
+    }
+
+    pub async fn list_snapshots<V: AsVolumeId>(&self, volume: &V) -> Result<Vec<VolumeSnapshot>> {
+        let volume_id = volume.as_volume_id();
+
+        self.make_volumes_request(reqwest::Method::GET, &format!("{volume_id}/snapshots"), ()).await
+    }
+
+    /// Requests an on-demand snapshot. The snapshot is taken asynchronously;
+    /// poll [`list_snapshots`](Self::list_snapshots) to see when it completes.
+    pub async fn create_snapshot<V: AsVolumeId>(&self, volume: &V) -> Result<()> {
+        let volume_id = volume.as_volume_id();
+
+        self.make_volumes_request(reqwest::Method::POST, &format!("{volume_id}/snapshots"), ()).await.map(ignore_body)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_create_volume() {
+    let (client, transport) = super::canned_client(200, include_str!("../../entities/volume_test_data.json"));
+
+    let volume = client.volumes().create(CreateVolumeRequest {
+        name: "data".to_string(),
+        region: "ord".to_string(),
+        ..Default::default()
+    }).await.unwrap();
+    assert_eq!(volume.id, "vol_4yl2v6o3d1r8xk9m");
+
+    let requests = transport.requests.lock().unwrap();
+    let (method, url, _, body) = &requests[0];
+    assert_eq!(method, http::Method::POST);
+    assert_eq!(url, "https://flaps.test/v1/apps/my-app/volumes");
+    // Unset options are left to the API's defaults
+    assert_eq!(body, r#"{"name":"data","region":"ord"}"#);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_get_volume() {
+    let (client, transport) = super::canned_client(200, include_str!("../../entities/volume_test_data.json"));
+
+    let volume = client.volumes().get(&"vol_4yl2v6o3d1r8xk9m").await.unwrap();
+    assert_eq!(volume.size_gb, 3);
+    assert_eq!(volume.attached_machine.as_deref(), Some("3d8d9e1c5e2089"));
+
+    let requests = transport.requests.lock().unwrap();
+    let (method, url, _, _) = &requests[0];
+    assert_eq!(method, http::Method::GET);
+    assert_eq!(url, "https://flaps.test/v1/apps/my-app/volumes/vol_4yl2v6o3d1r8xk9m");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_extend_volume() {
+    let (client, transport) = super::canned_client(200, concat!(r#"{"needs_restart":true,"volume":"#, include_str!("../../entities/volume_test_data.json"), "}"));
+
+    let res = client.volumes().extend(&"vol_4yl2v6o3d1r8xk9m", 10).await.unwrap();
+    assert!(res.needs_restart);
+    assert_eq!(res.volume.id, "vol_4yl2v6o3d1r8xk9m");
+
+    let requests = transport.requests.lock().unwrap();
+    let (method, url, _, body) = &requests[0];
+    assert_eq!(method, http::Method::PUT);
+    assert_eq!(url, "https://flaps.test/v1/apps/my-app/volumes/vol_4yl2v6o3d1r8xk9m/extend");
+    assert_eq!(body, r#"{"size_gb":10}"#);
+}
@@ -0,0 +1,153 @@
+use std::{collections::VecDeque, time::Duration};
+
+use futures::Stream;
+
+use super::{Client, FlapsError, Result};
+use crate::entities::machine::{Machine, MachineEvent, State};
+
+const WATCH_INITIAL_INTERVAL: Duration = Duration::from_millis(500);
+const WATCH_MAX_INTERVAL: Duration = Duration::from_millis(5000);
+const WATCH_MULTIPLIER: f64 = 1.5;
+
+/// An item of the stream returned by [`Client::watch`].
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum MachineWatchEvent {
+    /// An event that wasn't present in the previous snapshot of the machine.
+    Event(MachineEvent),
+    StateChanged { from: State, to: State },
+}
+
+struct WatchState {
+    client: Client,
+    machine_id: String,
+    state: State,
+    /// Timestamp of the newest event seen so far. Events at or before it have already been yielded.
+    last_timestamp: i64,
+    interval: Duration,
+    pending: VecDeque<MachineWatchEvent>,
+    done: bool,
+}
+
+/// Compares a new snapshot of a machine against what has been seen so far,
+/// returning the new events (oldest first) followed by any state transition.
+fn diff_snapshot(state: &mut State, last_timestamp: &mut i64, machine: &Machine) -> Vec<MachineWatchEvent> {
+    let mut new_events: Vec<_> = machine.events.iter()
+        .filter(|e| e.timestamp > *last_timestamp)
+        .cloned()
+        .collect();
+    new_events.sort_by_key(|e| e.timestamp);
+    // Events are identified by their timestamp; drop repeats of the same one.
+    new_events.dedup_by_key(|e| e.timestamp);
+
+    if let Some(last) = new_events.last() {
+        *last_timestamp = last.timestamp;
+    }
+
+    let mut out: Vec<_> = new_events.into_iter().map(MachineWatchEvent::Event).collect();
+    if machine.state != *state {
+        out.push(MachineWatchEvent::StateChanged {
+            from: std::mem::replace(state, machine.state.clone()),
+            to: machine.state.clone(),
+        });
+    }
+    out
+}
+
+impl Client {
+    /// Watches a machine for new events and state transitions, starting from the given snapshot.
+    ///
+    /// The machine is polled with [`get`](Self::get), backing off while nothing changes or while
+    /// polling fails with a [retryable](FlapsError::is_retryable) error. The stream ends once the
+    /// machine is destroyed or can no longer be found, yielding a final transition to
+    /// [`State::Destroyed`] if it wasn't seen, or after yielding the first error that isn't retryable.
+    pub fn watch(&self, machine: &Machine) -> impl Stream<Item = Result<MachineWatchEvent>> + Send + 'static {
+        let initial = WatchState {
+            client: self.clone(),
+            machine_id: machine.id.clone(),
+            state: machine.state.clone(),
+            last_timestamp: machine.events.iter().map(|e| e.timestamp).max().unwrap_or(0),
+            interval: WATCH_INITIAL_INTERVAL,
+            pending: VecDeque::new(),
+            done: machine.state == State::Destroyed,
+        };
+
+        futures::stream::unfold(initial, |mut st| async move {
+            loop {
+                if let Some(ev) = st.pending.pop_front() {
+                    return Some((Ok(ev), st));
+                }
+                if st.done {
+                    return None;
+                }
+
+                tokio::time::sleep(st.interval).await;
+
+                let machine = match st.client.get(&st.machine_id).await {
+                    Ok(m) => m,
+                    // Destroyed machines are eventually removed altogether
+                    Err(FlapsError::NotFound(_)) => {
+                        if st.state != State::Destroyed {
+                            st.pending.push_back(MachineWatchEvent::StateChanged {
+                                from: std::mem::replace(&mut st.state, State::Destroyed),
+                                to: State::Destroyed,
+                            });
+                        }
+                        st.done = true;
+                        continue;
+                    },
+                    Err(e) if e.is_retryable() => {
+                        st.interval = st.interval.mul_f64(WATCH_MULTIPLIER).min(WATCH_MAX_INTERVAL);
+                        continue;
+                    },
+                    Err(e) => {
+                        st.done = true;
+                        return Some((Err(e), st));
+                    },
+                };
+
+                let events = diff_snapshot(&mut st.state, &mut st.last_timestamp, &machine);
+                st.interval = if events.is_empty() {
+                    st.interval.mul_f64(WATCH_MULTIPLIER).min(WATCH_MAX_INTERVAL)
+                } else {
+                    WATCH_INITIAL_INTERVAL
+                };
+                st.pending.extend(events);
+                st.done = machine.state == State::Destroyed;
+            }
+        })
+    }
+}
+
+#[test]
+fn test_diff_snapshot() {
+    let machine: Machine = serde_json::from_value(serde_json::json!({
+        "id": "m1", "name": "m1", "state": "stopped", "region": "ord",
+        "image_ref": {"registry": "registry-1.docker.io", "repository": "library/nginx", "tag": "latest", "digest": null, "labels": {}},
+        "instance_id": "i1", "version": null, "private_ip": "fdaa::1",
+        "created_at": "2023-06-01T00:00:00Z", "updated_at": "2023-06-01T00:00:00Z",
+        "config": null, "checks": [], "nonce": "",
+        "events": [
+            {"type": "exit", "status": "stopped", "request": null, "source": "flyd", "timestamp": 30},
+            {"type": "start", "status": "started", "request": null, "source": "flyd", "timestamp": 20},
+            {"type": "start", "status": "started", "request": null, "source": "flyd", "timestamp": 20},
+            {"type": "launch", "status": "created", "request": null, "source": "user", "timestamp": 10},
+        ],
+    })).unwrap();
+
+    let mut state = State::Started;
+    let mut last_timestamp = 10;
+    let events = diff_snapshot(&mut state, &mut last_timestamp, &machine);
+
+    let timestamps: Vec<_> = events.iter().filter_map(|e| match e {
+        MachineWatchEvent::Event(ev) => Some(ev.timestamp),
+        _ => None,
+    }).collect();
+    assert_eq!(timestamps, vec![20, 30]);
+    assert!(matches!(events.last(), Some(MachineWatchEvent::StateChanged { from: State::Started, to: State::Stopped })));
+    assert_eq!(last_timestamp, 30);
+    assert_eq!(state, State::Stopped);
+
+    // Nothing new the second time around
+    assert!(diff_snapshot(&mut state, &mut last_timestamp, &machine).is_empty());
+}
@@ -0,0 +1,609 @@
+//! Reading and writing `fly.toml`, and turning it into the machine [`Config`]s that a deploy would launch.
+//! Enabled by the `appconfig` feature.
+//!
+//! ```
+//! # use flyio_api::appconfig::AppConfig;
+//! let app = AppConfig::from_toml(r#"
+//!     app = "my-app"
+//!
+//!     [build]
+//!     image = "registry.fly.io/my-app:deployment-1"
+//!
+//!     [processes]
+//!     web = "bundle exec puma"
+//!     worker = "bin/worker --queue default"
+//!
+//!     [http_service]
+//!     internal_port = 8080
+//!     processes = ["web"]
+//! "#).unwrap();
+//!
+//! let configs = app.machine_configs().unwrap();
+//! assert_eq!(configs["web"].services.len(), 1);
+//! assert_eq!(configs["worker"].init.as_ref().unwrap().cmd, ["bin/worker", "--queue", "default"]);
+//! ```
+
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::entities::{
+    FlyctlDuration,
+    machine::{
+        Check, Config, Guest, HttpHeader, HttpOptions, Init, Mount, Port, Service, ServiceConcurrency, SetSizeError, Static, StopConfig, TlsOptions,
+        MACHINE_CONFIG_METADATA_KEY_FLY_PLATFORM_VERSION, MACHINE_CONFIG_METADATA_KEY_FLY_PROCESS_GROUP,
+        MACHINE_FLY_PLATFORM_VERSION_2, MACHINE_PROCESS_GROUP_APP,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum AppConfigError {
+    #[error("Invalid fly.toml: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Failed to write fly.toml: {0}")]
+    Write(#[from] toml::ser::Error),
+    #[error(transparent)]
+    InvalidSize(#[from] SetSizeError),
+    #[error("Invalid memory size '{0}', expected e.g. 512mb or 2gb")]
+    InvalidMemory(String),
+}
+
+/// A `fly.toml` file.
+///
+/// Keys that this crate doesn't know about, such as `[experimental]`, are kept in `extra`
+/// fields, so that [`to_toml`](Self::to_toml) writes them back out.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct AppConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kill_signal: Option<String>,
+    /// Plain numbers are read as seconds.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "duration_or_seconds")]
+    pub kill_timeout: Option<FlyctlDuration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<Build>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deploy: Option<Deploy>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Process group names and their commands. Without any, there's a single `app` group that runs the image's command.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub processes: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_service: Option<HttpService>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ServiceConfig>,
+    /// Either `[mounts]` or `[[mounts]]`.
+    #[serde(skip_serializing_if = "Vec::is_empty", deserialize_with = "one_or_many")]
+    pub mounts: Vec<MountConfig>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, CheckConfig>,
+    /// Either `[statics]` or `[[statics]]`.
+    #[serde(skip_serializing_if = "Vec::is_empty", deserialize_with = "one_or_many")]
+    pub statics: Vec<StaticConfig>,
+    /// Either `[vm]` or `[[vm]]`.
+    #[serde(skip_serializing_if = "Vec::is_empty", deserialize_with = "one_or_many")]
+    pub vm: Vec<VmConfig>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Build {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dockerfile: Option<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Deploy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+/// `auto_stop_machines`, which is either a bool or one of `off`, `stop` and `suspend`.
+/// Machine configs only record whether it's enabled, so `suspend` is treated like `stop`.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum AutoStop {
+    Enabled(bool),
+    Mode(String),
+}
+
+impl AutoStop {
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            AutoStop::Enabled(enabled) => *enabled,
+            AutoStop::Mode(mode) => mode != "off",
+        }
+    }
+}
+
+/// `[http_service]`: HTTP on port 80 and HTTPS on port 443, forwarded to `internal_port`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct HttpService {
+    pub internal_port: u16,
+    pub force_https: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_stop_machines: Option<AutoStop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_start_machines: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_machines_running: Option<i32>,
+    /// Process groups that get this service. Empty means all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<ServiceConcurrency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_options: Option<TlsOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_options: Option<HttpOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckConfig>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+/// An entry of `[[services]]`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ServiceConfig {
+    pub protocol: String,
+    pub internal_port: u16,
+    /// Process groups that get this service. Empty means all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_stop_machines: Option<AutoStop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_start_machines: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_machines_running: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<Port>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<ServiceConcurrency>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tcp_checks: Vec<CheckConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub http_checks: Vec<CheckConfig>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+/// A health check, either top-level in `[checks]` or attached to a service.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CheckConfig {
+    /// `tcp` or `http`. Checks attached to a service get their type from the list they're in.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    /// Defaults to the service's internal port.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Plain numbers are read as milliseconds, for this and the other durations.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "duration_or_millis")]
+    pub interval: Option<FlyctlDuration>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "duration_or_millis")]
+    pub timeout: Option<FlyctlDuration>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "duration_or_millis")]
+    pub grace_period: Option<FlyctlDuration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_skip_verify: Option<bool>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Process groups that get this check. Only used by top-level checks; empty means all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+/// An entry of `[[mounts]]`, mounting the volume named `source` at `destination`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MountConfig {
+    pub source: String,
+    pub destination: String,
+    /// Process groups that get this mount. Empty means all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<String>,
+    /// Size of volumes created for new machines, e.g. `10gb`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_size: Option<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+/// An entry of `[[statics]]`, serving the files at `guest_path` under `url_prefix`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct StaticConfig {
+    pub guest_path: String,
+    pub url_prefix: String,
+    /// Process groups that serve these files. Empty means all of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+/// A memory size, either a number of megabytes or a string such as `512mb` or `2gb`.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Memory {
+    Mb(i32),
+    Size(String),
+}
+
+impl Memory {
+    pub fn to_mb(&self) -> Result<i32, AppConfigError> {
+        let size = match self {
+            Memory::Mb(mb) => return Ok(*mb),
+            Memory::Size(size) => size,
+        };
+        let lower = size.trim().to_ascii_lowercase();
+        let (number, unit) = match lower.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => lower.split_at(i),
+            None => (lower.as_str(), ""),
+        };
+        let multiplier = match unit.trim() {
+            "" | "m" | "mb" | "mib" => 1,
+            "g" | "gb" | "gib" => 1024,
+            _ => return Err(AppConfigError::InvalidMemory(size.clone())),
+        };
+        number.parse::<i32>().ok()
+            .and_then(|n| n.checked_mul(multiplier))
+            .ok_or_else(|| AppConfigError::InvalidMemory(size.clone()))
+    }
+}
+
+/// An entry of `[[vm]]`, sizing the machines of the process groups it applies to.
+/// `cpu_kind`, `cpus` and `memory` override the ones from `size`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct VmConfig {
+    /// A preset such as `shared-cpu-2x`. Defaults to `shared-cpu-1x`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Memory>,
+    /// Process groups this applies to. Empty means every group without a `[[vm]]` entry of its own.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<String>,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+impl VmConfig {
+    fn to_guest(&self) -> Result<Guest, AppConfigError> {
+        let mut guest = Guest::from_size(self.size.as_deref().unwrap_or("shared-cpu-1x"))?;
+        if let Some(cpu_kind) = &self.cpu_kind {
+            guest.cpu_kind = cpu_kind.clone().into();
+        }
+        if let Some(cpus) = self.cpus {
+            guest.cpus = cpus;
+        }
+        if let Some(memory) = &self.memory {
+            guest.memory_mb = memory.to_mb()?;
+        }
+        Ok(guest)
+    }
+}
+
+fn one_or_many<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(items) => items,
+        OneOrMany::One(item) => vec![item],
+    })
+}
+
+fn duration_or_number<'de, D: Deserializer<'de>>(deserializer: D, unit: Duration) -> Result<Option<FlyctlDuration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DurationOrNumber {
+        Number(u32),
+        Duration(FlyctlDuration),
+    }
+    Ok(Some(match DurationOrNumber::deserialize(deserializer)? {
+        DurationOrNumber::Number(n) => (unit * n).into(),
+        DurationOrNumber::Duration(d) => d,
+    }))
+}
+
+fn duration_or_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<FlyctlDuration>, D::Error> {
+    duration_or_number(deserializer, Duration::from_secs(1))
+}
+
+fn duration_or_millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<FlyctlDuration>, D::Error> {
+    duration_or_number(deserializer, Duration::from_millis(1))
+}
+
+/// Whether a section limited to `processes` applies to `group`.
+fn applies_to(processes: &[String], group: &str) -> bool {
+    processes.is_empty() || processes.iter().any(|p| p == group)
+}
+
+/// Splits a process command into arguments like a shell would, honouring quotes and backslashes.
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                current.extend(chars.next());
+                in_arg = true;
+            },
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            },
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            },
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            },
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+impl CheckConfig {
+    fn to_check(&self, type_: Option<&str>, internal_port: Option<u16>) -> Check {
+        Check {
+            port: self.port.or(internal_port).map(i32::from),
+            type_: self.type_.clone().or_else(|| type_.map(str::to_string)),
+            interval: self.interval,
+            timeout: self.timeout,
+            grace_period: self.grace_period,
+            http_method: self.method.clone(),
+            http_path: self.path.clone(),
+            http_protocol: self.protocol.clone(),
+            http_skip_tls_verify: self.tls_skip_verify,
+            http_headers: self.headers.iter().map(|(name, value)| HttpHeader {
+                name: name.clone(),
+                value: vec![value.clone()],
+                ..Default::default()
+            }).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl HttpService {
+    fn to_service(&self) -> Service {
+        let port = |port: u16, handlers: &[&str], force_https: bool, tls_options: Option<TlsOptions>| Port {
+            port: Some(port),
+            handlers: handlers.iter().map(|h| h.to_string()).collect(),
+            force_https,
+            tls_options,
+            http_options: self.http_options.clone(),
+            ..Default::default()
+        };
+        Service {
+            protocol: "tcp".to_string(),
+            internal_port: i32::from(self.internal_port),
+            autostop: self.auto_stop_machines.as_ref().map(AutoStop::is_enabled),
+            autostart: self.auto_start_machines,
+            min_machines_running: self.min_machines_running,
+            ports: vec![
+                port(80, &["http"], self.force_https, None),
+                port(443, &["tls", "http"], false, self.tls_options.clone()),
+            ],
+            checks: self.checks.iter().map(|c| c.to_check(Some("http"), Some(self.internal_port))).collect(),
+            concurrency: self.concurrency.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl ServiceConfig {
+    fn to_service(&self) -> Service {
+        let port = Some(self.internal_port);
+        Service {
+            protocol: self.protocol.clone(),
+            internal_port: i32::from(self.internal_port),
+            autostop: self.auto_stop_machines.as_ref().map(AutoStop::is_enabled),
+            autostart: self.auto_start_machines,
+            min_machines_running: self.min_machines_running,
+            ports: self.ports.clone(),
+            checks: self.tcp_checks.iter().map(|c| c.to_check(Some("tcp"), port))
+                .chain(self.http_checks.iter().map(|c| c.to_check(Some("http"), port)))
+                .collect(),
+            concurrency: self.concurrency.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn from_toml(toml: &str) -> Result<Self, AppConfigError> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, AppConfigError> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// The process groups in `[processes]`, or just `app` if there are none.
+    pub fn process_groups(&self) -> Vec<String> {
+        match self.processes.is_empty() {
+            true => vec![MACHINE_PROCESS_GROUP_APP.to_string()],
+            false => self.processes.keys().cloned().collect(),
+        }
+    }
+
+    /// One machine config for each of the [`process_groups`](Self::process_groups).
+    pub fn machine_configs(&self) -> Result<BTreeMap<String, Config>, AppConfigError> {
+        self.process_groups().into_iter().map(|group| {
+            let config = self.machine_config(&group)?;
+            Ok((group, config))
+        }).collect()
+    }
+
+    /// The machine config for machines in a process group, with the `fly_process_group` metadata set.
+    ///
+    /// Like flyctl, `FLY_PROCESS_GROUP` and `PRIMARY_REGION` are added to the environment.
+    /// The image comes from `[build]`, so it's empty unless `image` is set there. Without a `[vm]` section that
+    /// applies to the group, the guest is left unset and the Machines API picks the size.
+    pub fn machine_config(&self, group: &str) -> Result<Config, AppConfigError> {
+        let mut env = self.env.clone();
+        env.insert("FLY_PROCESS_GROUP".to_string(), group.to_string());
+        if let Some(region) = &self.primary_region {
+            env.insert("PRIMARY_REGION".to_string(), region.clone());
+        }
+        let metadata = BTreeMap::from([
+            (MACHINE_CONFIG_METADATA_KEY_FLY_PLATFORM_VERSION.to_string(), MACHINE_FLY_PLATFORM_VERSION_2.to_string()),
+            (MACHINE_CONFIG_METADATA_KEY_FLY_PROCESS_GROUP.to_string(), group.to_string()),
+        ]);
+        let cmd = self.processes.get(group).map(|command| split_command(command)).unwrap_or_default();
+
+        let checks: BTreeMap<String, Check> = self.checks.iter()
+            .filter(|(_, check)| applies_to(&check.processes, group))
+            .map(|(name, check)| (name.clone(), check.to_check(None, None)))
+            .collect();
+
+        let vm = self.vm.iter().find(|vm| vm.processes.iter().any(|p| p == group))
+            .or_else(|| self.vm.iter().find(|vm| vm.processes.is_empty()));
+
+        Ok(Config {
+            image: self.build.as_ref().and_then(|b| b.image.clone()).unwrap_or_default(),
+            env: Some(env),
+            metadata: Some(metadata),
+            init: (!cmd.is_empty()).then(|| Init { cmd, ..Default::default() }),
+            services: self.http_service.iter()
+                .filter(|s| applies_to(&s.processes, group))
+                .map(HttpService::to_service)
+                .chain(self.services.iter().filter(|s| applies_to(&s.processes, group)).map(ServiceConfig::to_service))
+                .collect(),
+            mounts: self.mounts.iter()
+                .filter(|m| applies_to(&m.processes, group))
+                .map(|m| Mount::from_vol_name(m.source.clone(), m.destination.clone()))
+                .collect(),
+            checks: (!checks.is_empty()).then_some(checks),
+            guest: vm.map(VmConfig::to_guest).transpose()?,
+            statics: self.statics.iter()
+                .filter(|s| applies_to(&s.processes, group))
+                .map(|s| Static { guest_path: s.guest_path.clone(), url_prefix: s.url_prefix.clone(), ..Default::default() })
+                .collect(),
+            stop_config: (self.kill_signal.is_some() || self.kill_timeout.is_some()).then(|| StopConfig {
+                timeout: self.kill_timeout,
+                signal: self.kill_signal.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+#[test]
+fn test_split_command() {
+    assert_eq!(split_command("bundle exec  puma -C config/puma.rb"), ["bundle", "exec", "puma", "-C", "config/puma.rb"]);
+    assert_eq!(split_command(r#"sh -c "echo 'hi there'" '' a\ b"#), ["sh", "-c", "echo 'hi there'", "", "a b"]);
+}
+
+#[test]
+fn test_machine_configs() {
+    let app = AppConfig::from_toml(include_str!("fly_test_data.toml")).unwrap();
+    let configs = app.machine_configs().unwrap();
+    assert_eq!(configs.keys().collect::<Vec<_>>(), ["web", "worker"]);
+
+    let web = &configs["web"];
+    assert_eq!(web.image, "registry.fly.io/my-app:deployment-1");
+    assert_eq!(web.metadata.as_ref().unwrap()[MACHINE_CONFIG_METADATA_KEY_FLY_PROCESS_GROUP], "web");
+    assert_eq!(web.env.as_ref().unwrap()["PRIMARY_REGION"], "ord");
+    assert_eq!(web.init.as_ref().unwrap().cmd, ["bundle", "exec", "puma"]);
+    assert_eq!(web.services.len(), 2);
+    assert!(web.services[0].ports[0].force_https);
+    assert_eq!(web.services[0].autostop, Some(true));
+    assert_eq!(web.services[0].checks[0].http_path.as_deref(), Some("/healthz"));
+    assert_eq!(web.services[1].checks[0].interval, Some(Duration::from_secs(15).into()));
+    assert_eq!(web.mounts[0].vol_name(), Some("data"));
+    assert_eq!(web.stop_config.as_ref().unwrap().timeout, Some(Duration::from_secs(30).into()));
+    assert!(web.checks.as_ref().unwrap().contains_key("alive"));
+    assert_eq!(web.statics[0].guest_path, "/rails/public");
+    let guest = web.guest.as_ref().unwrap();
+    assert_eq!((guest.cpu_kind.as_ref(), guest.cpus, guest.memory_mb), ("shared", 2, 1024));
+    assert_eq!(web.validate(), Ok(()));
+
+    let worker = &configs["worker"];
+    assert!(worker.services.is_empty() && worker.mounts.is_empty());
+    assert!(worker.checks.is_none());
+    assert!(worker.statics.is_empty());
+    let guest = worker.guest.as_ref().unwrap();
+    assert_eq!((guest.cpu_kind.as_ref(), guest.cpus, guest.memory_mb), ("performance", 1, 2048));
+
+    // Without a [vm] section, the API picks the size
+    assert_eq!(AppConfig::default().machine_config("app").unwrap().guest, None);
+    let app = AppConfig::from_toml("[vm]\nsize = \"shared-cpu-3x\"\n").unwrap();
+    assert!(matches!(app.machine_config("app"), Err(AppConfigError::InvalidSize(_))));
+    let app = AppConfig::from_toml("[vm]\nmemory = \"lots\"\n").unwrap();
+    assert!(matches!(app.machine_config("app"), Err(AppConfigError::InvalidMemory(_))));
+}
+
+#[test]
+fn test_memory() {
+    assert_eq!(Memory::Mb(512).to_mb().unwrap(), 512);
+    assert_eq!(Memory::Size("1gb".to_string()).to_mb().unwrap(), 1024);
+    assert_eq!(Memory::Size("512 MB".to_string()).to_mb().unwrap(), 512);
+    assert_eq!(Memory::Size("2048".to_string()).to_mb().unwrap(), 2048);
+    assert!(Memory::Size("1tb".to_string()).to_mb().is_err());
+}
+
+#[test]
+fn test_toml_round_trip() {
+    let app = AppConfig::from_toml(include_str!("fly_test_data.toml")).unwrap();
+    assert_eq!(app.vm[0].memory, Some(Memory::Size("1gb".to_string())));
+
+    let written = app.to_toml().unwrap();
+    assert_eq!(AppConfig::from_toml(&written).unwrap(), app);
+
+    // A single `[mounts]` table is read like a one-element `[[mounts]]` array
+    let app = AppConfig::from_toml("[mounts]\nsource = \"data\"\ndestination = \"/data\"\n").unwrap();
+    assert_eq!(app.mounts[0].destination, "/data");
+}
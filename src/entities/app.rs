@@ -0,0 +1,31 @@
+use super::Extra;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct App {
+    pub id: String,
+    pub name: String,
+    /// Only returned when fetching a single app
+    pub status: Option<String>,
+    /// Only returned when fetching a single app
+    pub organization: Option<AppOrganization>,
+    /// Only returned when listing apps
+    pub machine_count: Option<i32>,
+    /// Only returned when listing apps
+    pub network: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct AppOrganization {
+    pub name: String,
+    pub slug: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[test]
+fn test_app_round_trip() {
+    let app: App = super::assert_round_trips(include_str!("app_test_data.json"));
+    assert_eq!(app.organization.unwrap().extra["internal_numeric_id"], 123456);
+}
@@ -14,7 +14,14 @@ impl Serialize for GoTime {
     where
         S: Serializer,
     {
-        serializer.serialize_str(self.0.to_rfc3339().as_str())
+        // Like Go's RFC3339Nano: `Z` for UTC, and trailing zeros trimmed from the fraction
+        let s = self.0.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true);
+        let Some(dot) = s.find('.') else {
+            return serializer.serialize_str(&s);
+        };
+        let offset = dot + s[dot..].find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len() - dot);
+        let fraction = s[dot..offset].trim_end_matches('0').trim_end_matches('.');
+        serializer.serialize_str(&format!("{}{}{}", &s[..dot], fraction, &s[offset..]))
     }
 }
 
@@ -101,13 +108,17 @@ fn format_fixed_point(n: u128, decimal_idx: usize) -> TrimmedStr<32> {
     let mut sb = arrayvec::ArrayString::<32>::new();
     let mut n = n;
     let mut decimal_idx = decimal_idx;
-    while n > 0 {
+    // Always write every fractional digit, so that the decimal point ends up in the right place
+    loop {
         sb.push(((n % 10) as u8 + b'0') as char);
         n /= 10;
         if decimal_idx == 1 {
             sb.push('.');
         }
         decimal_idx = decimal_idx.saturating_sub(1);
+        if n == 0 && decimal_idx == 0 {
+            break;
+        }
     }
     if sb.ends_with('.') {
         sb.push('0');
@@ -121,12 +132,12 @@ fn format_fixed_point(n: u128, decimal_idx: usize) -> TrimmedStr<32> {
         s
     };
 
-    let last_number = s.rfind(|c| !matches!(c, '0'|'.'));
-    if let Some(last_number) = last_number {
-        TrimmedStr(s, 0..last_number+1)
-    } else {
-        TrimmedStr(s, 0..0)
-    }
+    // Only trim zeros after the decimal point
+    let end = match s.contains('.') {
+        true => s.trim_end_matches('0').trim_end_matches('.').len(),
+        false => s.len(),
+    };
+    TrimmedStr(s, 0..end)
 }
 
 fn parse_fixed_point(s: &str, shift_amt: usize) -> Result<u64, InvalidDurationFormatError> {
@@ -176,6 +187,25 @@ struct TestCase {
     ns_part: u32,
 }
 
+#[test]
+fn test_format_fixed_point() {
+    // Zeros before the decimal point are significant
+    assert_eq!(&*format_fixed_point(10_000_000_000, 9), "10");
+    assert_eq!(&*format_fixed_point(120_000_000_000, 9), "120");
+    // Leading fractional zeros keep the digits in place
+    assert_eq!(&*format_fixed_point(50_000_000, 9), "0.05");
+    assert_eq!(&*format_fixed_point(1_500_000_000, 9), "1.5");
+    assert_eq!(&*format_fixed_point(0, 9), "0");
+}
+
+#[test]
+fn test_go_time_serde() {
+    for s in ["2023-11-17T10:04:12Z", "2023-11-17T10:04:30.5Z", "2023-11-17T10:04:11.827Z", "2023-11-17T11:04:12.000001+01:00"] {
+        let t: GoTime = serde_json::from_value(serde_json::json!(s)).unwrap();
+        assert_eq!(serde_json::to_value(t).unwrap(), s);
+    }
+}
+
 #[test]
 fn test_parse_go_durations() {
 
@@ -222,13 +252,17 @@ fn test_duration_round_trip() {
     println!("s: {s}");
     let d2 = s.parse::<FlyctlDuration>().unwrap();
     assert_eq!(d.0, d2.0);
+
+    for s in ["10s", "30s", "1m0s", "1m0.05s", "2h0m10s"] {
+        assert_eq!(s.parse::<FlyctlDuration>().unwrap().to_string(), s);
+    }
 }
 
-impl ToString for FlyctlDuration {
-    fn to_string(&self) -> String {
+impl Display for FlyctlDuration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let ns = self.0.as_nanos();
         if ns == 0 {
-            return "0s".to_string();
+            return f.pad("0s");
         }
 
         let hrs = self.0.as_secs() / (60 * 60);
@@ -254,7 +288,7 @@ impl ToString for FlyctlDuration {
             let ns = self.as_nanos() - mins_as_secs * 1_000_000_000;
             sb.push_str(&format!("{}s", format_fixed_point(ns, 9)));
         }
-        sb
+        f.pad(&sb)
     }
 }
 impl std::str::FromStr for FlyctlDuration {
@@ -400,7 +434,7 @@ impl Serialize for GoDuration {
     where
         S: Serializer,
     {
-        serializer.serialize_i64(self.as_nanos().min(0).max(i64::MAX as u128) as i64)
+        serializer.serialize_i64(self.as_nanos().min(i64::MAX as u128) as i64)
     }
 }
 
@@ -498,4 +532,22 @@ impl<'de> Visitor<'de> for UnixTimeVisitor {
             E: serde::de::Error, {
         Ok(UnixTime(std::time::UNIX_EPOCH + std::time::Duration::from_secs(v)))
     }
-}
\ No newline at end of file
+}
+#[test]
+fn test_go_duration_serde() {
+    let d = GoDuration(std::time::Duration::from_millis(1500));
+    assert_eq!(serde_json::to_value(d).unwrap(), 1_500_000_000i64);
+    assert_eq!(serde_json::from_value::<GoDuration>(serde_json::json!(1_500_000_000i64)).unwrap(), d);
+
+    // Too long for an i64 of nanoseconds, so saturated like Go's time.Duration
+    let d = GoDuration(std::time::Duration::from_secs(u64::MAX));
+    assert_eq!(serde_json::to_value(d).unwrap(), i64::MAX);
+}
+
+#[test]
+fn test_display_flyctl_duration() {
+    let d = FlyctlDuration::from(std::time::Duration::from_secs(90));
+    assert_eq!(format!("timeout {d}"), "timeout 1m30s");
+    assert_eq!(format!("[{:>6}]", d), "[ 1m30s]");
+    assert_eq!(format!("[{:<3}]", FlyctlDuration::default()), "[0s ]");
+}
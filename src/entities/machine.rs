@@ -5,28 +5,36 @@ use std::{
 
 use phf::phf_map;
 use thiserror::Error;
-use super::GoTime;
+use super::{Extra, GoTime};
 
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Machine {
     pub id: String,
     pub name: String,
     pub state: State,
     pub region: String,
+    #[serde(default)]
     pub image_ref: ImageRef,
     /// InstanceID is unique for each version of the machine
+    #[serde(default)]
     pub instance_id: String,
     pub version: Option<String>,
     /// PrivateIP is the internal 6PN address of the machine.
+    #[serde(default)]
     pub private_ip: String,
     pub created_at: GoTime,
     pub updated_at: GoTime,
     pub config: Option<Config>,
+    #[serde(default)]
     pub events: Vec<MachineEvent>,
+    #[serde(default)]
     pub checks: Vec<CheckStatus>,
-    #[serde(rename = "nonce")]
+    /// Only set when the machine is leased
+    #[serde(rename = "nonce", default, skip_serializing_if = "String::is_empty")]
     pub lease_nonce: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 pub const MACHINE_CONFIG_METADATA_KEY_FLY_PLATFORM_VERSION: &str = "fly_platform_version";
@@ -86,12 +94,15 @@ impl Machine {
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq)]
+#[serde(default)]
 pub struct ImageRef {
     pub registry: String,
     pub repository: String,
     pub tag: Option<String>,
     pub digest: Option<String>,
     pub labels: HashMap<String, String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 impl ImageRef {
@@ -177,20 +188,29 @@ impl ImageRef {
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
+#[serde(default)]
 pub struct MachineEvent {
     #[serde(rename = "type")]
     pub type_: String,
     pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub request: Option<MachineRequest>, // TODO: Is this optional?
     pub source: String,
     pub timestamp: i64,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
+#[serde(default)]
 pub struct MachineRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub exit_event: Option<MachineExitEvent>, // TODO: Are these optional?
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub monitor_event: Option<MachineMonitorEvent>,
     pub restart_count: i32,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Debug, Error)]
@@ -213,11 +233,15 @@ impl MachineRequest {
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
+#[serde(default)]
 pub struct MachineMonitorEvent {
     pub exit_event: Option<MachineExitEvent>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
+#[serde(default)]
 pub struct MachineExitEvent {
     pub exit_code: i32,
     pub guest_exit_code: i32,
@@ -227,6 +251,8 @@ pub struct MachineExitEvent {
     pub restarting: bool,
     pub signal: i32,
     pub exited_at: Option<super::GoTime>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[repr(C)]
@@ -247,9 +273,12 @@ pub struct CheckStatus {
     pub status: ConsulCheckStatus,
     pub output: String,
     pub updated_at: Option<super::GoTime>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Config {
 
     pub env: Option<std::collections::BTreeMap<String, String>>,
@@ -263,6 +292,7 @@ pub struct Config {
 
     pub image: String,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub schedule: Option<String>,
     pub auto_destroy: bool,
     pub restart: Restart,
@@ -273,48 +303,47 @@ pub struct Config {
     pub standbys: Vec<String>,
 
     pub stop_config: Option<StopConfig>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Init {
     pub exec: Vec<String>,
     pub entrypoint: Vec<String>,
     pub cmd: Vec<String>,
     pub tty: bool,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Mount {
     pub encrypted: Option<bool>,
     pub path: String,
     pub size_gb: Option<i32>,
     volume: Option<String>,
     name: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 impl Mount {
-    fn private_default() -> Self {
-        Self {
-            encrypted: None,
-            path: "".to_string(),
-            size_gb: None,
-            volume: None,
-            name: None,
-        }
-    }
     pub fn from_vol_id(vol_id: String, path: String) -> Self {
         Self {
             volume: Some(vol_id),
             path,
-            ..Self::private_default()
+            ..Self::default()
         }
     }
     pub fn from_vol_name(vol_name: String, path: String) -> Self {
         Self {
             name: Some(vol_name),
             path,
-            ..Self::private_default()
+            ..Self::default()
         }
     }
     pub fn vol_id(&self) -> Option<&str> {
@@ -325,7 +354,8 @@ impl Mount {
     }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Service {
     pub protocol: String,
     pub internal_port: i32,
@@ -337,70 +367,86 @@ pub struct Service {
     pub concurrency: Option<ServiceConcurrency>,
     // force_instance_key: String,
     // force_instance_description: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Metrics {
     pub port: i32,
     pub path: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Check {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub port: Option<i32>,
-    #[serde(rename = "type")]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     pub type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub interval: Option<super::FlyctlDuration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout: Option<super::FlyctlDuration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub grace_period: Option<super::FlyctlDuration>,
-    #[serde(rename = "method")]
+    #[serde(rename = "method", skip_serializing_if = "Option::is_none")]
     pub http_method: Option<String>,
-    #[serde(rename = "path")]
+    #[serde(rename = "path", skip_serializing_if = "Option::is_none")]
     pub http_path: Option<String>,
-    #[serde(rename = "protocol")]
+    #[serde(rename = "protocol", skip_serializing_if = "Option::is_none")]
     pub http_protocol: Option<String>,
-    #[serde(rename = "tls_skip_verify")]
+    #[serde(rename = "tls_skip_verify", skip_serializing_if = "Option::is_none")]
     pub http_skip_tls_verify: Option<bool>,
-    #[serde(rename = "headers")]
+    #[serde(rename = "headers", skip_serializing_if = "Vec::is_empty")]
     pub http_headers: Vec<HttpHeader>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Static {
     pub guest_path: String,
     pub url_prefix: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[repr(C)]
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
 #[serde(rename_all = "kebab-case")]
 pub enum RestartPolicy {
+    #[default]
     No,
     OnFailure,
     Always,
 }
 
-impl Default for RestartPolicy {
-    fn default() -> Self {
-        Self::No
-    }
-}
-
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Restart {
     pub policy: Option<RestartPolicy>,
     pub max_retries: i32,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Guest {
     pub cpu_kind: Cow<'static, str>,
     pub cpus: i32,
     pub memory_mb: i32,
     pub kernel_args: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum SetSizeError {
     #[error("invalid machine preset requested '{size}', expected to start with 'shared' or 'performance'")]
     InvalidPreset { size: String },
@@ -469,155 +515,754 @@ pub const MAX_MEMORY_MB_PER_CPU:        i32 = 8192;
 // TODO - Determine if we want allocate max memory allocation, or minimum per # cpus.
 #[allow(clippy::identity_op)]
 pub const MACHINE_PRESETS: phf::Map<&'static str, Guest> = phf_map!{
-    "shared-cpu-1x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 1, memory_mb: 1 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new()},
-    "shared-cpu-2x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 2, memory_mb: 2 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new()},
-    "shared-cpu-4x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 4, memory_mb: 4 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new()},
-    "shared-cpu-8x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 8, memory_mb: 8 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new()},
-
-    "performance-1x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 1, memory_mb: 1 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new()},
-    "performance-2x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 2, memory_mb: 2 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new()},
-    "performance-4x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 4, memory_mb: 4 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new()},
-    "performance-8x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 8, memory_mb: 8 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new()},
-    "performance-16x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 16,memory_mb: 16 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new()},
+    "shared-cpu-1x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 1, memory_mb: 1 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "shared-cpu-2x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 2, memory_mb: 2 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "shared-cpu-4x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 4, memory_mb: 4 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "shared-cpu-8x" => Guest {cpu_kind: Cow::Borrowed("shared"), cpus: 8, memory_mb: 8 * MIN_MEMORY_MB_PER_SHARED_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+
+    "performance-1x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 1, memory_mb: 1 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "performance-2x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 2, memory_mb: 2 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "performance-4x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 4, memory_mb: 4 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "performance-8x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 8, memory_mb: 8 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new(), extra: Extra::new()},
+    "performance-16x" => Guest {cpu_kind: Cow::Borrowed("performance"), cpus: 16,memory_mb: 16 * MIN_MEMORY_MB_PER_CPU, kernel_args: Vec::new(), extra: Extra::new()},
 };
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+pub const MACHINE_SCHEDULES: &[&str] = &["hourly", "daily", "weekly", "monthly"];
+pub const PORT_HANDLERS: &[&str] = &["http", "tls", "pg_tls", "proxy_proto", "edge_http"];
+const SERVICE_PROTOCOLS: &[&str] = &["tcp", "udp"];
+const CONCURRENCY_TYPES: &[&str] = &["connections", "requests"];
+const CHECK_TYPES: &[&str] = &["tcp", "http"];
+/// Memory is allocated in steps of this size.
+const MEMORY_MB_STEP: i32 = 256;
+
+/// A problem with one field of a [`Config`], found by [`Config::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{path}: {message}")]
+pub struct ConfigError {
+    /// The field's path in the config's JSON, e.g. `services[0].ports[1].start_port`.
+    pub path: String,
+    pub message: String,
+}
+
+/// Every problem [`Config::validate`] found, displayed as one message.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Error)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for ConfigErrors {
+    type Target = [ConfigError];
+    fn deref(&self) -> &[ConfigError] {
+        &self.0
+    }
+}
+
+impl IntoIterator for ConfigErrors {
+    type Item = ConfigError;
+    type IntoIter = std::vec::IntoIter<ConfigError>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl ConfigErrors {
+    fn add(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ConfigError { path: path.into(), message: message.into() });
+    }
+    fn check_port(&mut self, path: impl Into<String>, port: i32) {
+        if !(1..=i32::from(u16::MAX)).contains(&port) {
+            self.add(path, format!("{port} is not a valid port"));
+        }
+    }
+    fn check_one_of(&mut self, path: impl Into<String>, value: &str, allowed: &[&str]) {
+        if !allowed.contains(&value) {
+            self.add(path, format!("'{value}' is not one of {}", allowed.join(", ")));
+        }
+    }
+}
+
+impl Config {
+    /// Checks for mistakes that the Machines API would reject, without a round trip.
+    /// Returns every problem that was found, rather than only the first.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = ConfigErrors::default();
+
+        if self.image.is_empty() {
+            errors.add("image", "is required");
+        }
+        if let Some(schedule) = &self.schedule {
+            errors.check_one_of("schedule", schedule, MACHINE_SCHEDULES);
+        }
+        if self.restart.max_retries < 0 {
+            errors.add("restart.max_retries", "must not be negative");
+        }
+        if let Some(guest) = &self.guest {
+            guest.validate(&mut errors);
+        }
+        if let Some(metrics) = &self.metrics {
+            errors.check_port("metrics.port", metrics.port);
+        }
+
+        if self.mounts.len() > 1 {
+            errors.add("mounts", "only one volume can be mounted");
+        }
+        for (i, mount) in self.mounts.iter().enumerate() {
+            if mount.volume.is_none() && mount.name.is_none() {
+                errors.add(format!("mounts[{i}].volume"), "is required");
+            }
+            if !mount.path.starts_with('/') {
+                errors.add(format!("mounts[{i}].path"), "must be an absolute path");
+            } else if let Some(first) = self.mounts[..i].iter().position(|m| m.path == mount.path) {
+                errors.add(format!("mounts[{i}].path"), format!("is already used by mounts[{first}]"));
+            }
+        }
+
+        for (i, service) in self.services.iter().enumerate() {
+            service.validate(&format!("services[{i}]"), &mut errors);
+        }
+        for (name, check) in self.checks.iter().flatten() {
+            check.validate(&format!("checks.{name}"), &mut errors);
+        }
+        for (i, statik) in self.statics.iter().enumerate() {
+            if !statik.url_prefix.starts_with('/') {
+                errors.add(format!("statics[{i}].url_prefix"), "must start with '/'");
+            }
+            if !statik.guest_path.starts_with('/') {
+                errors.add(format!("statics[{i}].guest_path"), "must be an absolute path");
+            }
+        }
+
+        match errors.0.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+impl Guest {
+    fn validate(&self, errors: &mut ConfigErrors) {
+        let (min_per_cpu, max_per_cpu) = match self.cpu_kind.as_ref() {
+            "shared" => (MIN_MEMORY_MB_PER_SHARED_CPU, MAX_MEMORY_MB_PER_SHARED_CPU),
+            "performance" => (MIN_MEMORY_MB_PER_CPU, MAX_MEMORY_MB_PER_CPU),
+            kind => return errors.check_one_of("guest.cpu_kind", kind, &["shared", "performance"]),
+        };
+
+        let mut cpu_counts: Vec<i32> = MACHINE_PRESETS.values().filter(|g| g.cpu_kind == self.cpu_kind).map(|g| g.cpus).collect();
+        cpu_counts.sort();
+        if !cpu_counts.contains(&self.cpus) {
+            let counts: Vec<_> = cpu_counts.iter().map(i32::to_string).collect();
+            return errors.add("guest.cpus", format!("{} machines can have {} CPUs", self.cpu_kind, counts.join(", ")));
+        }
+
+        let (min, max) = (self.cpus * min_per_cpu, self.cpus * max_per_cpu);
+        if !(min..=max).contains(&self.memory_mb) {
+            errors.add("guest.memory_mb", format!("must be between {min} and {max} for {} {} CPUs", self.cpus, self.cpu_kind));
+        } else if self.memory_mb % MEMORY_MB_STEP != 0 {
+            errors.add("guest.memory_mb", format!("must be a multiple of {MEMORY_MB_STEP}"));
+        }
+    }
+}
+
+impl Service {
+    fn validate(&self, path: &str, errors: &mut ConfigErrors) {
+        errors.check_one_of(format!("{path}.protocol"), &self.protocol, SERVICE_PROTOCOLS);
+        errors.check_port(format!("{path}.internal_port"), self.internal_port);
+
+        for (i, port) in self.ports.iter().enumerate() {
+            let path = format!("{path}.ports[{i}]");
+            match (port.port, port.start_port, port.end_port) {
+                (Some(port), None, None) => errors.check_port(format!("{path}.port"), i32::from(port)),
+                (None, Some(start), Some(end)) => {
+                    errors.check_port(format!("{path}.start_port"), i32::from(start));
+                    if start > end {
+                        errors.add(format!("{path}.start_port"), format!("{start} is greater than end_port {end}"));
+                    }
+                },
+                _ => errors.add(path.clone(), "needs either port, or both start_port and end_port"),
+            }
+            for (j, handler) in port.handlers.iter().enumerate() {
+                errors.check_one_of(format!("{path}.handlers[{j}]"), handler, PORT_HANDLERS);
+            }
+        }
+
+        if let Some(concurrency) = &self.concurrency {
+            errors.check_one_of(format!("{path}.concurrency.type"), &concurrency.type_, CONCURRENCY_TYPES);
+            if concurrency.soft_limit > concurrency.hard_limit {
+                errors.add(format!("{path}.concurrency.soft_limit"), format!("{} is greater than hard_limit {}", concurrency.soft_limit, concurrency.hard_limit));
+            }
+        }
+        for (i, check) in self.checks.iter().enumerate() {
+            check.validate(&format!("{path}.checks[{i}]"), errors);
+        }
+    }
+}
+
+impl Check {
+    fn validate(&self, path: &str, errors: &mut ConfigErrors) {
+        if let Some(type_) = &self.type_ {
+            errors.check_one_of(format!("{path}.type"), type_, CHECK_TYPES);
+        }
+        if let Some(port) = self.port {
+            errors.check_port(format!("{path}.port"), port);
+        }
+        if self.type_.as_deref() == Some("http") && !self.http_path.as_deref().is_some_and(|p| p.starts_with('/')) {
+            errors.add(format!("{path}.path"), "http checks need a path starting with '/'");
+        }
+    }
+}
+
+/// One difference between two [`Config`]s, found by [`Config::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigChange {
+    Image { from: String, to: String },
+    EnvAdded { key: String, value: String },
+    EnvRemoved { key: String, value: String },
+    EnvChanged { key: String, from: String, to: String },
+    MetadataAdded { key: String, value: String },
+    MetadataRemoved { key: String, value: String },
+    MetadataChanged { key: String, from: String, to: String },
+    /// The CPU kind, CPU count or memory changed. Other guest fields are reported as [`Other`](Self::Other),
+    /// e.g. `guest.kernel_args`.
+    GuestResized { from: Option<Guest>, to: Option<Guest> },
+    /// Services are matched by protocol and internal port, so `index` is the position in the new config,
+    /// or in the old one for removed services.
+    ServiceAdded { index: usize, service: Service },
+    ServiceRemoved { index: usize, service: Service },
+    ServiceChanged { index: usize, from: Service, to: Service },
+    /// The same services are present, but in a different order.
+    ServicesReordered,
+    /// Any other field, compared as JSON. Missing fields are `null`.
+    Other { field: String, from: serde_json::Value, to: serde_json::Value },
+}
+
+/// The changes between two [`Config`]s. Its [`Display`] renders them one per line, flyctl style:
+/// `+` for additions, `-` for removals and `~` for changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff(pub Vec<ConfigChange>);
+
+impl std::ops::Deref for ConfigDiff {
+    type Target = Vec<ConfigChange>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for ConfigDiff {
+    type Item = ConfigChange;
+    type IntoIter = std::vec::IntoIter<ConfigChange>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Fields with their own [`ConfigChange`] variants.
+const DIFFED_FIELDS: &[&str] = &["image", "env", "metadata", "guest", "services"];
+/// Guest fields covered by [`ConfigChange::GuestResized`].
+const GUEST_SIZE_FIELDS: &[&str] = &["cpu_kind", "cpus", "memory_mb"];
+
+fn to_json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+/// The fields that differ between two JSON objects, over the keys of both. Missing fields are `null`.
+fn changed_fields<'a>(from: &'a serde_json::Value, to: &'a serde_json::Value) -> impl Iterator<Item = (&'a String, &'a serde_json::Value, &'a serde_json::Value)> {
+    let keys: std::collections::BTreeSet<&String> = from.as_object().into_iter().chain(to.as_object()).flat_map(|o| o.keys()).collect();
+    keys.into_iter().map(move |key| (key, &from[key], &to[key])).filter(|(_, from, to)| from != to)
+}
+
+fn diff_maps(
+    from: Option<&BTreeMap<String, String>>,
+    to: Option<&BTreeMap<String, String>>,
+    added: impl Fn(String, String) -> ConfigChange,
+    removed: impl Fn(String, String) -> ConfigChange,
+    changed: impl Fn(String, String, String) -> ConfigChange,
+    changes: &mut Vec<ConfigChange>,
+) {
+    let (from, to) = (from.cloned().unwrap_or_default(), to.cloned().unwrap_or_default());
+    for (key, value) in &from {
+        match to.get(key) {
+            None => changes.push(removed(key.clone(), value.clone())),
+            Some(new) if new != value => changes.push(changed(key.clone(), value.clone(), new.clone())),
+            Some(_) => {},
+        }
+    }
+    for (key, value) in to {
+        if !from.contains_key(&key) {
+            changes.push(added(key, value));
+        }
+    }
+}
+
+impl Config {
+    /// Lists what would change if this config were replaced by `other`, e.g. to preview a deploy.
+    pub fn diff(&self, other: &Config) -> ConfigDiff {
+        let mut changes = Vec::new();
+
+        if self.image != other.image {
+            changes.push(ConfigChange::Image { from: self.image.clone(), to: other.image.clone() });
+        }
+        diff_maps(self.env.as_ref(), other.env.as_ref(),
+            |key, value| ConfigChange::EnvAdded { key, value },
+            |key, value| ConfigChange::EnvRemoved { key, value },
+            |key, from, to| ConfigChange::EnvChanged { key, from, to },
+            &mut changes);
+        diff_maps(self.metadata.as_ref(), other.metadata.as_ref(),
+            |key, value| ConfigChange::MetadataAdded { key, value },
+            |key, value| ConfigChange::MetadataRemoved { key, value },
+            |key, from, to| ConfigChange::MetadataChanged { key, from, to },
+            &mut changes);
+        let size = |guest: &Option<Guest>| guest.as_ref().map(|g| (g.cpu_kind.clone(), g.cpus, g.memory_mb));
+        if size(&self.guest) != size(&other.guest) {
+            changes.push(ConfigChange::GuestResized { from: self.guest.clone(), to: other.guest.clone() });
+        }
+        // A missing guest has the default kernel args and extras
+        let (from, to) = (to_json(&self.guest.clone().unwrap_or_default()), to_json(&other.guest.clone().unwrap_or_default()));
+        for (field, from, to) in changed_fields(&from, &to).filter(|(k, ..)| !GUEST_SIZE_FIELDS.contains(&k.as_str())) {
+            changes.push(ConfigChange::Other { field: format!("guest.{field}"), from: from.clone(), to: to.clone() });
+        }
+        self.diff_services(other, &mut changes);
+
+        let (from, to) = (to_json(self), to_json(other));
+        for (field, from, to) in changed_fields(&from, &to).filter(|(k, ..)| !DIFFED_FIELDS.contains(&k.as_str())) {
+            changes.push(ConfigChange::Other { field: field.clone(), from: from.clone(), to: to.clone() });
+        }
+
+        ConfigDiff(changes)
+    }
+
+    fn diff_services(&self, other: &Config, changes: &mut Vec<ConfigChange>) {
+        let key = |s: &Service| (s.protocol.clone(), s.internal_port);
+        let mut matched = vec![false; self.services.len()];
+        let mut old_order = Vec::new();
+
+        for (index, service) in other.services.iter().enumerate() {
+            let old = (0..self.services.len()).find(|&i| !matched[i] && key(&self.services[i]) == key(service));
+            let Some(i) = old else {
+                changes.push(ConfigChange::ServiceAdded { index, service: service.clone() });
+                continue;
+            };
+            matched[i] = true;
+            old_order.push(i);
+            if self.services[i] != *service {
+                changes.push(ConfigChange::ServiceChanged { index, from: self.services[i].clone(), to: service.clone() });
+            }
+        }
+        for (index, service) in self.services.iter().enumerate().filter(|(i, _)| !matched[*i]) {
+            changes.push(ConfigChange::ServiceRemoved { index, service: service.clone() });
+        }
+        if old_order.windows(2).any(|w| w[0] > w[1]) {
+            changes.push(ConfigChange::ServicesReordered);
+        }
+    }
+}
+
+fn describe_guest(guest: &Option<Guest>) -> String {
+    match guest {
+        Some(g) => format!("{} {} CPU{}, {} MB", g.cpu_kind, g.cpus, if g.cpus == 1 { "" } else { "s" }, g.memory_mb),
+        None => "default".to_string(),
+    }
+}
+
+fn describe_service(service: &Service) -> String {
+    let ports: Vec<String> = service.ports.iter().map(|p| match (p.port, p.start_port, p.end_port) {
+        (Some(port), _, _) => port.to_string(),
+        (None, Some(start), Some(end)) => format!("{start}-{end}"),
+        _ => "?".to_string(),
+    }).collect();
+    format!("{} {} <- [{}]", service.protocol, service.internal_port, ports.join(", "))
+}
+
+impl Display for ConfigChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigChange::Image { from, to } => write!(f, "~ image: {from} -> {to}"),
+            ConfigChange::EnvAdded { key, value } => write!(f, "+ env.{key} = {value:?}"),
+            ConfigChange::EnvRemoved { key, value } => write!(f, "- env.{key} = {value:?}"),
+            ConfigChange::EnvChanged { key, from, to } => write!(f, "~ env.{key}: {from:?} -> {to:?}"),
+            ConfigChange::MetadataAdded { key, value } => write!(f, "+ metadata.{key} = {value:?}"),
+            ConfigChange::MetadataRemoved { key, value } => write!(f, "- metadata.{key} = {value:?}"),
+            ConfigChange::MetadataChanged { key, from, to } => write!(f, "~ metadata.{key}: {from:?} -> {to:?}"),
+            ConfigChange::GuestResized { from, to } => write!(f, "~ guest: {} -> {}", describe_guest(from), describe_guest(to)),
+            ConfigChange::ServiceAdded { index, service } => write!(f, "+ services[{index}]: {}", describe_service(service)),
+            ConfigChange::ServiceRemoved { index, service } => write!(f, "- services[{index}]: {}", describe_service(service)),
+            ConfigChange::ServiceChanged { index, from, to } => {
+                write!(f, "~ services[{index}]: {}", describe_service(to))?;
+                let (from, to) = (to_json(from), to_json(to));
+                for (field, old, new) in changed_fields(&from, &to) {
+                    write!(f, "\n    {field}: {old} -> {new}")?;
+                }
+                Ok(())
+            },
+            ConfigChange::ServicesReordered => write!(f, "~ services: reordered"),
+            ConfigChange::Other { field, from, to } => write!(f, "~ {field}: {from} -> {to}"),
+        }
+    }
+}
+
+impl Display for ConfigDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for change in &self.0 {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct DNSConfig {
     pub skip_registration: bool,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Process {
-    #[serde(rename = "exec")]
+    #[serde(rename = "exec", skip_serializing_if = "Vec::is_empty")]
     pub exec_override: Vec<String>,
-    #[serde(rename = "entrypoint")]
+    #[serde(rename = "entrypoint", skip_serializing_if = "Vec::is_empty")]
     pub entrypoint_override: Vec<String>,
-    #[serde(rename = "cmd")]
+    #[serde(rename = "cmd", skip_serializing_if = "Vec::is_empty")]
     pub cmd_override: Vec<String>,
-    #[serde(rename = "user")]
+    #[serde(rename = "user", skip_serializing_if = "String::is_empty")]
     pub user_override: String,
-    #[serde(rename = "env")]
+    #[serde(rename = "env", skip_serializing_if = "BTreeMap::is_empty")]
     pub extra_env: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct StopConfig {
     pub timeout: Option<super::FlyctlDuration>,
     pub signal: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct Port {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub start_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub end_port: Option<u16>,
     pub handlers: Vec<String>,
     pub force_https: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tls_options: Option<TlsOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub http_options: Option<HttpOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_proto_options: Option<ProxyProtoOptions>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct ServiceConcurrency {
     #[serde(rename = "type")]
     pub type_: String,
     pub hard_limit: i32,
     pub soft_limit: i32,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct HttpHeader {
     pub name: String,
+    #[serde(rename = "values", alias = "value")]
     pub value: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct TlsOptions {
     pub alpn: Vec<String>,
     pub versions: Vec<String>,
     pub default_self_signed: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct HttpOptions {
     pub compress: Option<bool>,
     pub response: Option<HttpResponseOptions>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, Hash)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq, Hash)]
+#[serde(default)]
 pub struct ProxyProtoOptions {
     pub version: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone, PartialEq)]
+#[serde(default)]
 pub struct HttpResponseOptions {
     pub headers: std::collections::BTreeMap<String, serde_json::Value>,
-}
-fn hash_jsvalue<H: std::hash::Hasher>(v: &serde_json::Value, state: &mut H) {
-    match v {
-        serde_json::Value::Null => {
-            state.write_u8(0);
-        },
-        serde_json::Value::Bool(b) => {
-            state.write_u8(1);
-            b.hash(state);
-        },
-        serde_json::Value::Number(n) => {
-            state.write_u8(2);
-            n.hash(state);
-        },
-        serde_json::Value::String(s) => {
-            state.write_u8(3);
-            s.hash(state);
-        },
-        serde_json::Value::Array(a) => {
-            state.write_u8(4);
-            for v in a {
-                hash_jsvalue(v, state);
-            }
-        },
-        serde_json::Value::Object(o) => {
-            state.write_u8(5);
-            let mut keys: Vec<_> = o.keys().collect();
-            keys.sort();
-            for k in keys {
-                k.hash(state);
-                hash_jsvalue(&o[k], state);
-            }
-        },
-    }
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 impl Hash for HttpResponseOptions {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         for (k, v) in &self.headers {
             k.hash(state);
-            hash_jsvalue(v, state);
+            super::hash_jsvalue(v, state);
         }
+        self.extra.hash(state);
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case")]
+/// The state of a machine, as reported by the Machines API.
+///
+/// States this crate doesn't know about deserialize to [`State::Unknown`] instead of failing,
+/// so that one machine in a new state doesn't break listing the whole app.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum State {
-    Destroyed,
-    Destroying,
+    Created,
+    Starting,
     Started,
+    Stopping,
     Stopped,
-    Created,
+    Suspending,
+    Suspended,
+    Replacing,
+    Replaced,
+    Destroying,
+    Destroyed,
+    Failed,
+    Unknown(String),
 }
 impl State {
+    /// Parses a known state name. Returns `None` for anything else.
     pub fn from_name(name: &str) -> Option<Self> {
-        // Gross hack, abuse serde to do the work for us
-        serde_json::from_str(&format!("\"{}\"", name)).ok()
+        Some(match name {
+            "created" => State::Created,
+            "starting" => State::Starting,
+            "started" => State::Started,
+            "stopping" => State::Stopping,
+            "stopped" => State::Stopped,
+            "suspending" => State::Suspending,
+            "suspended" => State::Suspended,
+            "replacing" => State::Replacing,
+            "replaced" => State::Replaced,
+            "destroying" => State::Destroying,
+            "destroyed" => State::Destroyed,
+            "failed" => State::Failed,
+            _ => return None,
+        })
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            State::Created => "created",
+            State::Starting => "starting",
+            State::Started => "started",
+            State::Stopping => "stopping",
+            State::Stopped => "stopped",
+            State::Suspending => "suspending",
+            State::Suspended => "suspended",
+            State::Replacing => "replacing",
+            State::Replaced => "replaced",
+            State::Destroying => "destroying",
+            State::Destroyed => "destroyed",
+            State::Failed => "failed",
+            State::Unknown(name) => name,
+        }
+    }
+    /// Whether the `wait` endpoint accepts this state as a target.
+    pub fn is_waitable(&self) -> bool {
+        matches!(self, State::Started | State::Stopped | State::Suspended | State::Destroyed)
     }
-    pub fn name(&self) -> String {
-        // Gross hack, abuse serde to do the work for us
-        serde_json::to_string(self).unwrap().trim_matches('"').to_string()
+    /// Whether the machine is on its way to another state, e.g. `starting` or `stopping`.
+    pub fn is_transitional(&self) -> bool {
+        matches!(self, State::Starting | State::Stopping | State::Suspending | State::Replacing | State::Destroying)
     }
 }
 impl Display for State {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.name())
+        f.write_str(self.name())
     }
-}
\ No newline at end of file
+}
+impl serde::Serialize for State {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+impl<'de> serde::Deserialize<'de> for State {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = Cow::<'de, str>::deserialize(deserializer)?;
+        Ok(State::from_name(&name).unwrap_or_else(|| State::Unknown(name.into_owned())))
+    }
+}
+
+#[test]
+fn test_state_serde() {
+    let states: Vec<State> = serde_json::from_str(r#"["suspended", "replacing", "failed", "hibernating"]"#).unwrap();
+    assert_eq!(states, vec![State::Suspended, State::Replacing, State::Failed, State::Unknown("hibernating".to_string())]);
+    assert_eq!(serde_json::to_string(&states).unwrap(), r#"["suspended","replacing","failed","hibernating"]"#);
+    assert_eq!(State::from_name("hibernating"), None);
+}
+
+#[test]
+fn test_machine_round_trip() {
+    use crate::api::flaps::LaunchMachineInput;
+
+    let json = include_str!("machine_test_data.json");
+    let machine: Machine = super::assert_round_trips(json);
+    assert_eq!(machine.state, State::Started);
+    assert_eq!(machine.lease_nonce, "a1b2c3d4e5f6");
+    assert_eq!(machine.extra["host_status"], "ok");
+
+    let mut config = machine.config.unwrap();
+    assert_eq!(config.services[0].checks[0].http_headers[0].value, ["my-app.fly.dev"]);
+    assert!(config.extra.contains_key("files"));
+    assert!(config.guest.as_ref().unwrap().extra.contains_key("gpu_kind"));
+
+    // Settings the crate doesn't know about survive being sent back in an update
+    config.env.get_or_insert_with(BTreeMap::new).insert("LOG_LEVEL".to_string(), "debug".to_string());
+    let input = LaunchMachineInput { config: Some(config), ..Default::default() };
+    let sent = serde_json::to_value(&input).unwrap()["config"].take();
+
+    let mut expected = serde_json::from_str::<serde_json::Value>(json).unwrap()["config"].take();
+    expected["env"]["LOG_LEVEL"] = "debug".into();
+    assert_eq!(sent, expected);
+}
+
+#[test]
+fn test_config_validate() {
+    let valid = Config {
+        image: "registry.fly.io/my-app:deployment-1".to_string(),
+        guest: Some(Guest::from_size("shared-cpu-2x").unwrap()),
+        mounts: vec![Mount::from_vol_id("vol_1".to_string(), "/data".to_string())],
+        ..Default::default()
+    };
+    assert_eq!(valid.validate(), Ok(()));
+
+    let mut config = valid.clone();
+    config.schedule = Some("yearly".to_string());
+    config.guest.as_mut().unwrap().memory_mb = 8192;
+    config.mounts.push(Mount::from_vol_name("data".to_string(), "/data".to_string()));
+    config.services.push(Service {
+        protocol: "tcp".to_string(),
+        internal_port: 8080,
+        ports: vec![Port {
+            start_port: Some(9000),
+            end_port: Some(8000),
+            handlers: vec!["tls".to_string(), "https".to_string()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    });
+
+    let paths: Vec<_> = config.validate().unwrap_err().into_iter().map(|e| e.path).collect();
+    assert_eq!(paths, [
+        "schedule",
+        "guest.memory_mb",
+        "mounts",
+        "mounts[1].path",
+        "services[0].ports[0].start_port",
+        "services[0].ports[0].handlers[1]",
+    ]);
+
+    config = valid;
+    config.guest = Some(Guest { cpu_kind: Cow::Borrowed("shared"), cpus: 3, memory_mb: 768, ..Default::default() });
+    assert_eq!(config.validate().unwrap_err()[0].to_string(), "guest.cpus: shared machines can have 1, 2, 4, 8 CPUs");
+    config.image = String::new();
+    assert_eq!(config.validate().unwrap_err().to_string(), "image: is required; guest.cpus: shared machines can have 1, 2, 4, 8 CPUs");
+}
+
+#[test]
+fn test_config_diff() {
+    let service = |internal_port| Service { protocol: "tcp".to_string(), internal_port, ..Default::default() };
+    let old = Config {
+        image: "registry.fly.io/my-app:deployment-1".to_string(),
+        env: Some(BTreeMap::from([("LOG_LEVEL".to_string(), "info".to_string()), ("OLD".to_string(), "1".to_string())])),
+        guest: Some(Guest::from_size("shared-cpu-1x").unwrap()),
+        services: vec![service(8080), service(9090)],
+        ..Default::default()
+    };
+    assert!(old.diff(&old).is_empty());
+
+    let mut new = old.clone();
+    new.image = "registry.fly.io/my-app:deployment-2".to_string();
+    new.env = Some(BTreeMap::from([("LOG_LEVEL".to_string(), "debug".to_string()), ("NEW".to_string(), "1".to_string())]));
+    new.guest = Some(Guest::from_size("shared-cpu-2x").unwrap());
+    new.services = vec![service(9090), service(8080)];
+    new.services[1].autostop = Some(true);
+    new.schedule = Some("daily".to_string());
+
+    let diff = old.diff(&new);
+    assert!(matches!(&diff[..], [
+        ConfigChange::Image { .. },
+        ConfigChange::EnvChanged { .. },
+        ConfigChange::EnvRemoved { .. },
+        ConfigChange::EnvAdded { .. },
+        ConfigChange::GuestResized { .. },
+        ConfigChange::ServiceChanged { index: 1, .. },
+        ConfigChange::ServicesReordered,
+        ConfigChange::Other { .. },
+    ]));
+    assert_eq!(diff.to_string(), "\
+~ image: registry.fly.io/my-app:deployment-1 -> registry.fly.io/my-app:deployment-2
+~ env.LOG_LEVEL: \"info\" -> \"debug\"
+- env.OLD = \"1\"
++ env.NEW = \"1\"
+~ guest: shared 1 CPU, 256 MB -> shared 2 CPUs, 512 MB
+~ services[1]: tcp 8080 <- []
+    autostop: null -> true
+~ services: reordered
+~ schedule: null -> \"daily\"
+");
+
+    new.services.pop();
+    assert!(old.diff(&new).contains(&ConfigChange::ServiceRemoved { index: 0, service: service(8080) }));
+
+    // Fields that are only in the old service are listed too
+    let mut autostop = old.clone();
+    autostop.services[0].autostop = Some(true);
+    assert_eq!(autostop.diff(&old).to_string(), "~ services[0]: tcp 8080 <- []\n    autostop: true -> null\n");
+
+    // Guest changes other than the size aren't a resize
+    let mut kernel_args = old.clone();
+    kernel_args.guest.as_mut().unwrap().kernel_args = vec!["quiet".to_string()];
+    assert_eq!(old.diff(&kernel_args).0, [ConfigChange::Other {
+        field: "guest.kernel_args".to_string(),
+        from: serde_json::json!([]),
+        to: serde_json::json!(["quiet"]),
+    }]);
+    let mut no_guest = old.clone();
+    no_guest.guest = None;
+    assert!(matches!(&old.diff(&no_guest)[..], [ConfigChange::GuestResized { to: None, .. }]));
+}
+
+#[test]
+fn test_restart_policy() {
+    assert_eq!(RestartPolicy::default(), RestartPolicy::No);
+    assert_eq!(serde_json::to_value(RestartPolicy::OnFailure).unwrap(), "on-failure");
+    assert_eq!(serde_json::from_value::<RestartPolicy>("always".into()).unwrap(), RestartPolicy::Always);
+}
@@ -3,17 +3,92 @@
 
 
 
-// pub mod app;
+pub mod app;
 
 pub mod machine;
 
-// pub mod org;
+pub mod volume;
+
+pub mod secret;
+
+pub mod org;
 
 mod go_time;
 pub use go_time::*;
 
+use std::{collections::BTreeMap, hash::{Hash, Hasher}, ops::{Deref, DerefMut}};
+
+/// Fields the API returned that this crate doesn't have a field for yet.
+///
+/// Every entity keeps them in an `extra` field and writes them back out when serialized,
+/// so reading a [`Config`](machine::Config) and passing it to an update doesn't drop settings.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(transparent)]
+pub struct Extra(pub BTreeMap<String, serde_json::Value>);
+
+impl Extra {
+    pub const fn new() -> Self {
+        Extra(BTreeMap::new())
+    }
+}
+
+impl Deref for Extra {
+    type Target = BTreeMap<String, serde_json::Value>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl DerefMut for Extra {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
 
-#[derive(Debug, Clone, serde::Deserialize)]
+impl Hash for Extra {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (k, v) in &self.0 {
+            k.hash(state);
+            hash_jsvalue(v, state);
+        }
+    }
+}
+
+pub(crate) fn hash_jsvalue<H: Hasher>(v: &serde_json::Value, state: &mut H) {
+    match v {
+        serde_json::Value::Null => {
+            state.write_u8(0);
+        },
+        serde_json::Value::Bool(b) => {
+            state.write_u8(1);
+            b.hash(state);
+        },
+        serde_json::Value::Number(n) => {
+            state.write_u8(2);
+            n.hash(state);
+        },
+        serde_json::Value::String(s) => {
+            state.write_u8(3);
+            s.hash(state);
+        },
+        serde_json::Value::Array(a) => {
+            state.write_u8(4);
+            for v in a {
+                hash_jsvalue(v, state);
+            }
+        },
+        serde_json::Value::Object(o) => {
+            state.write_u8(5);
+            let mut keys: Vec<_> = o.keys().collect();
+            keys.sort();
+            for k in keys {
+                k.hash(state);
+                hash_jsvalue(&o[k], state);
+            }
+        },
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ProcessStat {
     pub pid: i32,
     pub stime: u64,
@@ -22,11 +97,25 @@ pub struct ProcessStat {
     pub directory: String,
     pub cpu: u64,
     pub rss: u64,
+    #[serde(default)]
     pub listen_sockets: Vec<ListenSocket>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ListenSocket {
     pub proto: String,
     pub address: String,
-}
\ No newline at end of file
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Checks that `json` deserializes to `T` and serializes back without losing or changing anything.
+#[cfg(test)]
+fn assert_round_trips<T: serde::de::DeserializeOwned + serde::Serialize>(json: &str) -> T {
+    let parsed: T = serde_json::from_str(json).unwrap();
+    let original: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), original);
+    parsed
+}
@@ -0,0 +1,45 @@
+use super::Extra;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct Organization {
+    pub id: String,
+    pub slug: String,
+    pub name: String,
+    /// `PERSONAL` or `SHARED`
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub billing_status: Option<String>,
+    #[serde(default)]
+    pub members: Vec<OrganizationMember>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl Organization {
+    pub fn is_personal(&self) -> bool {
+        self.type_ == "PERSONAL"
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct OrganizationMember {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    /// `ADMIN` or `MEMBER`
+    pub role: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[test]
+fn test_organization_round_trip() {
+    let org: Organization = super::assert_round_trips(include_str!("org_test_data.json"));
+    assert!(!org.is_personal());
+    assert_eq!(org.extra["paid_plan"], true);
+    assert_eq!(org.members[0].extra["last_region"], "ord");
+
+    // Members are only fetched for some queries
+    let org: Organization = serde_json::from_str(r#"{"id": "o1", "slug": "acme", "name": "Acme Inc", "type": "SHARED", "billing_status": null}"#).unwrap();
+    assert!(org.members.is_empty());
+}
@@ -0,0 +1,63 @@
+use std::fmt::{Debug, Formatter};
+
+use super::{Extra, GoTime};
+
+/// A secret's plaintext value.
+///
+/// Its [`Debug`] implementation never prints the value, so secrets can't leak into logs
+/// through `{:?}`. Use [`expose`](Self::expose) to read it.
+#[derive(Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(transparent)]
+pub struct SecretValue(String);
+
+impl SecretValue {
+    pub fn new(value: String) -> Self {
+        SecretValue(value)
+    }
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for SecretValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretValue(<redacted>)")
+    }
+}
+
+impl From<String> for SecretValue {
+    fn from(value: String) -> Self {
+        SecretValue(value)
+    }
+}
+impl From<&str> for SecretValue {
+    fn from(value: &str) -> Self {
+        SecretValue(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Secret {
+    pub name: String,
+    /// Digest of the value, useful to detect changes without revealing the secret
+    pub digest: String,
+    pub created_at: Option<GoTime>,
+    /// Only present when the values were explicitly requested
+    pub value: Option<SecretValue>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[test]
+fn test_secret_debug_is_redacted() {
+    let secret = Secret {
+        name: "DATABASE_URL".to_string(),
+        digest: "abc123".to_string(),
+        created_at: None,
+        value: Some("postgres://user:hunter2@db".into()),
+        extra: Default::default(),
+    };
+    let debug = format!("{secret:?}");
+    assert!(debug.contains("DATABASE_URL"));
+    assert!(!debug.contains("hunter2"));
+}
@@ -0,0 +1,54 @@
+use super::{Extra, GoTime};
+use super::machine::Mount;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct Volume {
+    pub id: String,
+    pub name: String,
+    pub state: String,
+    pub size_gb: i32,
+    pub region: String,
+    pub zone: String,
+    pub encrypted: bool,
+    #[serde(rename = "attached_machine_id")]
+    pub attached_machine: Option<String>,
+    #[serde(rename = "attached_alloc_id")]
+    pub attached_allocation: Option<String>,
+    pub created_at: GoTime,
+    pub host_dedication_id: Option<String>,
+    pub snapshot_retention: Option<i32>,
+    pub auto_backup_enabled: Option<bool>,
+    pub host_status: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl Volume {
+    pub fn is_attached(&self) -> bool {
+        self.attached_machine.is_some()
+    }
+    /// Creates a [`Mount`] for this volume, to be placed in a machine's [`Config`](super::machine::Config).
+    pub fn mount(&self, path: String) -> Mount {
+        Mount::from_vol_id(self.id.clone(), path)
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct VolumeSnapshot {
+    pub id: String,
+    /// Size of the snapshot, in bytes
+    pub size: u64,
+    pub digest: String,
+    pub created_at: GoTime,
+    pub status: String,
+    pub retention_days: Option<i32>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[test]
+fn test_volume_round_trip() {
+    let volume: Volume = super::assert_round_trips(include_str!("volume_test_data.json"));
+    assert!(volume.is_attached());
+    assert_eq!(volume.extra["fstype"], "ext4");
+}
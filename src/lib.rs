@@ -3,4 +3,10 @@ pub mod api;
 
 pub mod patterns;
 
-pub mod entities;
\ No newline at end of file
+pub mod entities;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "appconfig")]
+pub mod appconfig;
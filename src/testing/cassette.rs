@@ -0,0 +1,430 @@
+//! Recording requests to a file once, and replaying them offline afterwards.
+
+use std::{collections::BTreeMap, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use serde_json::Value;
+
+use crate::api::flaps::{HeaderPair, Result, Transport, TransportResult};
+
+/// Request and response headers that are never written to a cassette.
+const REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+fn is_redacted(name: &str) -> bool {
+    REDACTED_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+/// The contents of a cassette file.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Cassettes ending in `.yaml` or `.yml` are YAML, anything else is JSON.
+    fn is_yaml(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml")
+    }
+
+    fn from_slice(path: &Path, bytes: &[u8]) -> std::io::Result<Cassette> {
+        match Self::is_yaml(path) {
+            true => serde_yaml::from_slice(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            false => Ok(serde_json::from_slice(bytes)?),
+        }
+    }
+
+    fn to_vec(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        match Self::is_yaml(path) {
+            true => serde_yaml::to_string(self).map(String::into_bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            false => Ok(serde_json::to_vec_pretty(self)?),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub body: Body,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Body,
+}
+
+/// A request or response body. JSON bodies are kept as JSON, so that cassettes are readable and diffable.
+/// Bodies that aren't UTF-8 are base64-encoded, so that they replay byte for byte.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+    #[default]
+    Empty,
+    Json(Value),
+    Text(String),
+    Base64(String),
+}
+
+impl Body {
+    fn from_bytes(bytes: &[u8]) -> Body {
+        use base64::Engine;
+
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Body::Empty;
+        }
+        if let Ok(json) = serde_json::from_slice(bytes) {
+            return Body::Json(json);
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Body::Text(text.to_string()),
+            Err(_) => Body::Base64(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        }
+    }
+
+    fn to_bytes(&self) -> bytes::Bytes {
+        use base64::Engine;
+
+        match self {
+            Body::Empty => bytes::Bytes::new(),
+            Body::Json(json) => serde_json::to_vec(json).unwrap_or_default().into(),
+            Body::Text(text) => text.clone().into(),
+            Body::Base64(encoded) => base64::engine::general_purpose::STANDARD.decode(encoded)
+                .unwrap_or_else(|e| panic!("invalid base64 body in cassette: {e}"))
+                .into(),
+        }
+    }
+}
+
+impl RecordedRequest {
+    fn new(method: &http::Method, url: &url::Url, json: &str, headers: &[HeaderPair]) -> Self {
+        RecordedRequest {
+            method: method.to_string(),
+            path: url.path().to_string(),
+            query: url.query().map(str::to_string),
+            body: Body::from_bytes(json.as_bytes()),
+            headers: headers.iter()
+                .filter(|h| !is_redacted(h.name()))
+                .map(|h| (h.name().to_ascii_lowercase(), h.value().to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// Which parts of a request must be equal to a recorded one for it to be replayed.
+/// The method and path always have to match.
+#[derive(Debug, Clone)]
+pub struct MatchRules {
+    pub query: bool,
+    pub body: bool,
+    /// Request headers that have to match, in lowercase. None by default.
+    pub headers: Vec<String>,
+    /// Only replay interactions in the order they were recorded, rather than the first unplayed match.
+    pub in_order: bool,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules { query: true, body: true, headers: Vec::new(), in_order: false }
+    }
+}
+
+impl MatchRules {
+    fn matches(&self, recorded: &RecordedRequest, request: &RecordedRequest) -> bool {
+        recorded.method == request.method
+            && recorded.path == request.path
+            && (!self.query || recorded.query == request.query)
+            && (!self.body || recorded.body == request.body)
+            && self.headers.iter().all(|h| recorded.headers.get(h) == request.headers.get(h))
+    }
+}
+
+enum Mode {
+    /// `write_lock` keeps writes in order, so that an older snapshot never overwrites a newer one.
+    Record { inner: Arc<dyn Transport>, path: PathBuf, write_lock: tokio::sync::Mutex<()> },
+    Replay { rules: MatchRules, played: Mutex<Vec<bool>> },
+}
+
+/// A [`Transport`] that records interactions to a cassette file, or replays them from one.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use flyio_api::{api::flaps::{Client, FlapsSettings, HttpTransport}, testing::CassetteTransport};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let settings = FlapsSettings::builder().app_name("my-app").build();
+///
+/// // Once, against the real API:
+/// let http = Arc::new(HttpTransport::new(reqwest::Client::new(), &std::env::var("FLY_API_TOKEN")?));
+/// let client = Client::with_transport(settings.clone(), Arc::new(CassetteTransport::record(http, "tests/cassettes/launch.json")))?;
+///
+/// // Afterwards, offline:
+/// let client = Client::with_transport(settings, Arc::new(CassetteTransport::replay("tests/cassettes/launch.json")?))?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Cassettes are JSON, or YAML if the path ends in `.yaml` or `.yml`. Recording writes the file after every interaction.
+///
+/// The `Authorization` header is added by the inner transport, so it never reaches the cassette. Auth and cookie headers
+/// passed to this transport or returned in responses are dropped too. Request and response bodies are recorded as-is,
+/// so don't record secrets you don't want to commit.
+///
+/// When replaying, a request that doesn't match an unplayed interaction **panics**, listing what was recorded,
+/// so that tests fail at the request that diverged rather than somewhere downstream.
+pub struct CassetteTransport {
+    mode: Mode,
+    cassette: Mutex<Cassette>,
+}
+
+impl CassetteTransport {
+    /// Sends requests with `inner` and records them to `path`, replacing any existing cassette.
+    pub fn record<P: Into<PathBuf>>(inner: Arc<dyn Transport>, path: P) -> Self {
+        CassetteTransport {
+            mode: Mode::Record { inner, path: path.into(), write_lock: tokio::sync::Mutex::new(()) },
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    /// Replays the cassette at `path`, in JSON or YAML, with the default [`MatchRules`].
+    pub fn replay<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::replay_with(path, MatchRules::default())
+    }
+
+    pub fn replay_with<P: AsRef<Path>>(path: P, rules: MatchRules) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let cassette = Cassette::from_slice(path, &std::fs::read(path)?)?;
+        Ok(Self::from_cassette(cassette, rules))
+    }
+
+    /// Replays a cassette that's already in memory.
+    pub fn from_cassette(cassette: Cassette, rules: MatchRules) -> Self {
+        let played = Mutex::new(vec![false; cassette.interactions.len()]);
+        CassetteTransport {
+            mode: Mode::Replay { rules, played },
+            cassette: Mutex::new(cassette),
+        }
+    }
+
+    /// The interactions recorded so far, or the ones being replayed.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    /// Panics if any recorded interaction hasn't been replayed. Does nothing while recording.
+    pub fn assert_all_played(&self) {
+        if let Mode::Replay { played, .. } = &self.mode {
+            let unplayed: Vec<_> = {
+                let cassette = self.cassette.lock().unwrap();
+                let played = played.lock().unwrap();
+                played.iter().zip(&cassette.interactions)
+                    .filter(|(played, _)| !**played)
+                    .map(|(_, i)| format!("{} {}", i.request.method, i.request.path))
+                    .collect()
+            };
+            assert!(unplayed.is_empty(), "cassette interactions were never replayed: {unplayed:?}");
+        }
+    }
+
+    fn replay_request(&self, rules: &MatchRules, played: &Mutex<Vec<bool>>, request: RecordedRequest) -> TransportResult {
+        let found = {
+            let cassette = self.cassette.lock().unwrap();
+            let mut played = played.lock().unwrap();
+
+            let candidate = match rules.in_order {
+                true => played.iter().position(|p| !p).filter(|&i| rules.matches(&cassette.interactions[i].request, &request)),
+                false => (0..played.len()).find(|&i| !played[i] && rules.matches(&cassette.interactions[i].request, &request)),
+            };
+            match candidate {
+                Some(index) => {
+                    played[index] = true;
+                    Ok(cassette.interactions[index].response.clone())
+                },
+                None => Err(cassette.interactions.iter().zip(played.iter())
+                    .map(|(i, played)| format!(
+                        "{}{} {}{}",
+                        if *played { "(played) " } else { "" },
+                        i.request.method,
+                        i.request.path,
+                        i.request.query.as_ref().map(|q| format!("?{q}")).unwrap_or_default(),
+                    ))
+                    .collect::<Vec<_>>()),
+            }
+        };
+        // Only panic once the locks are released, so that they aren't poisoned for the rest of the test
+        let response = found.unwrap_or_else(|recorded| {
+            panic!("request not found in cassette: {request:#?}\nrecorded interactions:\n  {}", recorded.join("\n  "))
+        });
+
+        let mut headers = http::HeaderMap::new();
+        for (name, value) in &response.headers {
+            if let (Ok(name), Ok(value)) = (http::HeaderName::from_bytes(name.as_bytes()), http::HeaderValue::from_str(value)) {
+                headers.insert(name, value);
+            }
+        }
+        let status = http::StatusCode::from_u16(response.status).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+        TransportResult::new(status, headers, response.body.to_bytes())
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for CassetteTransport {
+    async fn make_request(&self, user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> Result<TransportResult> {
+        let request = RecordedRequest::new(&method, &url, &json, &headers);
+
+        match &self.mode {
+            Mode::Replay { rules, played } => Ok(self.replay_request(rules, played, request)),
+            Mode::Record { inner, path, write_lock } => {
+                let res = inner.make_request(user_agent, method, url, json, headers).await?;
+
+                let response = RecordedResponse {
+                    status: res.status_code.as_u16(),
+                    headers: res.headers.iter()
+                        .filter(|(name, _)| !is_redacted(name.as_str()))
+                        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                        .collect(),
+                    body: Body::from_bytes(&res.body),
+                };
+                self.cassette.lock().unwrap().interactions.push(Interaction { request, response });
+
+                // Snapshot under the write lock, so the last write always has every interaction
+                let _write = write_lock.lock().await;
+                let contents = self.cassette.lock().unwrap().to_vec(path);
+                let written = match contents {
+                    Ok(contents) => {
+                        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                            let _ = tokio::fs::create_dir_all(dir).await;
+                        }
+                        tokio::fs::write(path, contents).await
+                    },
+                    Err(e) => Err(e),
+                };
+                written.unwrap_or_else(|e| panic!("failed to write cassette {}: {e}", path.display()));
+                Ok(res)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_record_and_replay() {
+    use crate::api::flaps::{Client, FlapsSettings, StopMachineInput};
+
+    let path = std::env::temp_dir().join(format!("flyio-api-{}-cassette.json", std::process::id()));
+    let settings = FlapsSettings::builder().base_url(super::FAKE_BASE_URL).app_name("my-app").build();
+
+    let fake = super::FakeFlaps::new();
+    fake.create_app("my-app");
+    let recorder = Arc::new(CassetteTransport::record(Arc::new(fake), &path));
+    let client = Client::with_transport(settings.clone(), recorder.clone()).unwrap();
+
+    let launched = client.launch(super::test_launch_input()).await.unwrap();
+    let stop = StopMachineInput { id: launched.id.clone(), signal: "SIGINT".to_string(), timeout: std::time::Duration::from_secs(5).into() };
+    client.stop(stop.clone(), None).await.unwrap();
+    let stopped = client.get(&launched.id).await.unwrap();
+    assert_eq!(recorder.cassette().interactions.len(), 3);
+
+    let replayer = Arc::new(CassetteTransport::replay(&path).unwrap());
+    let _ = std::fs::remove_file(&path);
+    let client = Client::with_transport(settings, replayer.clone()).unwrap();
+
+    assert_eq!(client.launch(super::test_launch_input()).await.unwrap().id, launched.id);
+    client.stop(stop, None).await.unwrap();
+    assert_eq!(client.get(&launched.id).await.unwrap().state, stopped.state);
+    replayer.assert_all_played();
+}
+
+#[cfg(test)]
+#[tokio::test]
+#[should_panic(expected = "request not found in cassette")]
+async fn test_replay_miss() {
+    use crate::api::flaps::{Client, FlapsSettings};
+
+    let replayer = CassetteTransport::from_cassette(Cassette::default(), MatchRules::default());
+    let client = Client::with_transport(FlapsSettings::builder().app_name("my-app").build(), Arc::new(replayer)).unwrap();
+    let _ = client.get(&"m1").await;
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_redaction_and_yaml() {
+    use crate::api::flaps::{Client, FlapsSettings, HttpTransport};
+
+    struct SetsCookie;
+    #[async_trait::async_trait]
+    impl Transport for SetsCookie {
+        async fn make_request(&self, _: &str, _: http::Method, _: url::Url, _: String, _: Vec<HeaderPair>) -> Result<TransportResult> {
+            let mut headers = http::HeaderMap::new();
+            headers.insert(http::header::SET_COOKIE, "session=secret".parse().unwrap());
+            headers.insert("fly-request-id", "req-1".parse().unwrap());
+            Ok(TransportResult::new(http::StatusCode::OK, headers, bytes::Bytes::new()))
+        }
+    }
+
+    // The real token is added by the HTTP transport, below the recorder
+    let fake = super::FakeFlaps::new();
+    fake.create_app("my-app");
+    let server = fake.bind().await.unwrap();
+    let path = std::env::temp_dir().join(format!("flyio-api-{}-cassette.yaml", std::process::id()));
+    let http = Arc::new(HttpTransport::new(reqwest::Client::new(), "secret-token"));
+    let settings = FlapsSettings::builder().base_url(server.base_url()).app_name("my-app").build();
+    let client = Client::with_transport(settings.clone(), Arc::new(CassetteTransport::record(http, &path))).unwrap();
+    let launched = client.launch(super::test_launch_input()).await.unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("interactions:\n"));
+    assert!(!contents.contains("secret-token"));
+    let replayer = Arc::new(CassetteTransport::replay(&path).unwrap());
+    let _ = std::fs::remove_file(&path);
+    let client = Client::with_transport(settings, replayer.clone()).unwrap();
+    assert_eq!(client.launch(super::test_launch_input()).await.unwrap().id, launched.id);
+    replayer.assert_all_played();
+
+    // Auth and cookie headers that do pass through the recorder are dropped
+    let path = std::env::temp_dir().join(format!("flyio-api-{}-cassette-redacted.json", std::process::id()));
+    let recorder = CassetteTransport::record(Arc::new(SetsCookie), &path);
+    let headers = vec![HeaderPair("Authorization", "Bearer secret".to_string()), HeaderPair("x-custom", "1".to_string())];
+    recorder.make_request("test", http::Method::GET, url::Url::parse("http://localhost/v1/apps").unwrap(), String::new(), headers).await.unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    let interaction = &recorder.cassette().interactions[0];
+    assert_eq!(interaction.request.headers, BTreeMap::from([("x-custom".to_string(), "1".to_string())]));
+    assert_eq!(interaction.response.headers, BTreeMap::from([("fly-request-id".to_string(), "req-1".to_string())]));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_replay_miss_keeps_cassette_usable() {
+    use crate::api::flaps::{Client, FlapsSettings};
+
+    let replayer = Arc::new(CassetteTransport::from_cassette(Cassette::default(), MatchRules::default()));
+    let client = Client::with_transport(FlapsSettings::builder().app_name("my-app").build(), replayer.clone()).unwrap();
+    assert!(tokio::spawn(async move { client.get(&"m1").await }).await.unwrap_err().is_panic());
+
+    assert!(replayer.cassette().interactions.is_empty());
+    replayer.assert_all_played();
+}
+
+#[test]
+fn test_binary_bodies() {
+    let bytes = [0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe];
+    let body = Body::from_bytes(&bytes);
+    assert_eq!(body, Body::Base64("H4sIAP/+".to_string()));
+
+    let body: Body = serde_json::from_str(&serde_json::to_string(&body).unwrap()).unwrap();
+    assert_eq!(body.to_bytes().as_ref(), bytes);
+    assert_eq!(Body::from_bytes(b"plain text"), Body::Text("plain text".to_string()));
+}
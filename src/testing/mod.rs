@@ -0,0 +1,838 @@
+//! An in-memory fake of the Machines API, for testing code built on [`Client`] without
+//! talking to Fly.io. Enabled by the `testing` feature.
+//!
+//! [`FakeFlaps`] keeps track of apps, machines, leases and queued exec results, and answers
+//! requests the way the real service does: machines move through `created` → `started` →
+//! `stopped` → `destroyed`, `wait` blocks until the state is reached or times out with a 408,
+//! leased machines reject mutations without the right nonce, and errors come back as the same
+//! JSON bodies ([`RawApiError`](crate::api::flaps::RawApiError)) with the same status codes.
+//!
+//! Use it directly as a [`Transport`] with [`FakeFlaps::client`], or serve it over HTTP on a
+//! local port with [`FakeFlaps::bind`].
+//!
+//! Volumes, secrets and GraphQL requests are not implemented and return 404s.
+//!
+//! To test against recordings of the real API instead, see [`CassetteTransport`].
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::api::flaps::{Client, FlapsSettings, HeaderPair, Transport, TransportResult};
+use crate::entities::machine::{Machine, State};
+
+mod server;
+pub use server::FakeFlapsServer;
+mod cassette;
+pub use cassette::{Body, Cassette, CassetteTransport, Interaction, MatchRules, RecordedRequest, RecordedResponse};
+
+/// The base URL used by clients from [`FakeFlaps::client`]. Never resolved.
+pub const FAKE_BASE_URL: &str = "http://fake-flaps.test";
+
+const DEFAULT_LEASE_TTL: i64 = 30;
+
+/// Result of an exec, queued with [`FakeFlaps::push_exec_result`].
+#[derive(Debug, Clone, Default)]
+pub struct FakeExecResult {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+struct FakeLease {
+    nonce: String,
+    expires_at: i64,
+    owner: String,
+}
+
+struct FakeMachine {
+    id: String,
+    name: String,
+    state: State,
+    region: String,
+    instance_id: String,
+    config: Value,
+    created_at: String,
+    updated_at: String,
+    events: Vec<Value>,
+    lease: Option<FakeLease>,
+    cordoned: bool,
+    exec_results: VecDeque<FakeExecResult>,
+}
+
+struct FakeApp {
+    id: String,
+    org_slug: String,
+    network: String,
+    machines: BTreeMap<String, FakeMachine>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    apps: BTreeMap<String, FakeApp>,
+    counter: u64,
+}
+
+/// An in-memory Machines API. Cloning is cheap, and clones share the same state.
+#[derive(Clone, Default)]
+pub struct FakeFlaps {
+    state: Arc<Mutex<FakeState>>,
+    /// Woken on every change, so that pending `wait` requests can re-check their machine.
+    changed: Arc<tokio::sync::Notify>,
+}
+
+/// An error response, shaped like the real API's.
+struct FakeError {
+    status: u16,
+    error: String,
+    message: Option<String>,
+}
+
+impl FakeError {
+    fn new(status: u16, error: impl Into<String>) -> Self {
+        FakeError { status, error: error.into(), message: None }
+    }
+    fn not_found(what: &str) -> Self {
+        FakeError::new(404, format!("{what} not found"))
+    }
+}
+
+type FakeResult = Result<Value, FakeError>;
+
+/// The parts of a request that handlers look at, besides the path.
+struct FakeRequest<'a> {
+    method: &'a http::Method,
+    query: BTreeMap<String, String>,
+    body: Value,
+    nonce: Option<&'a str>,
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+fn rfc3339_now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Splits an image reference such as `registry.fly.io/app:tag` into the API's `image_ref` shape.
+fn image_ref(image: &str) -> Value {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+    let (name, tag) = match name.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, Some(tag)),
+        _ => (name, None),
+    };
+    let (registry, repository) = match name.split_once('/') {
+        Some((registry, repository)) if registry.contains('.') || registry.contains(':') => (registry, repository.to_string()),
+        Some(_) => ("docker-hub-mirror.fly.io", name.to_string()),
+        None => ("docker-hub-mirror.fly.io", format!("library/{name}")),
+    };
+    json!({
+        "registry": registry,
+        "repository": repository,
+        "tag": tag,
+        "digest": digest,
+        "labels": {},
+    })
+}
+
+impl FakeMachine {
+    fn to_json(&self) -> Value {
+        let image = self.config.get("image").and_then(Value::as_str).unwrap_or_default();
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "state": self.state,
+            "region": self.region,
+            "image_ref": image_ref(image),
+            "instance_id": self.instance_id,
+            "version": self.instance_id,
+            "private_ip": format!("fdaa:0:1::{}", &self.id[self.id.len() - 4..]),
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+            "config": self.config,
+            "events": self.events,
+            "checks": [],
+            "nonce": self.lease.as_ref().map(|l| l.nonce.as_str()).unwrap_or_default(),
+        })
+    }
+
+    fn active_lease(&self) -> Option<&FakeLease> {
+        self.lease.as_ref().filter(|l| l.expires_at > unix_now())
+    }
+
+    /// Mutations on a leased machine must carry the lease's nonce.
+    fn check_lease(&self, nonce: Option<&str>) -> Result<(), FakeError> {
+        match self.active_lease() {
+            Some(lease) if Some(lease.nonce.as_str()) != nonce => Err(FakeError {
+                status: 412,
+                error: format!("machine ID {} lease currently held by {}, expires at {}", self.id, lease.owner, lease.expires_at),
+                message: Some("lease mismatch".to_string()),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn transition(&mut self, state: State, event_type: &str, source: &str) {
+        self.state = state;
+        self.updated_at = rfc3339_now();
+        // Newest first, like the real API. Timestamps are in milliseconds and must be unique.
+        let last = self.events.first().and_then(|e| e["timestamp"].as_i64()).unwrap_or(0);
+        let timestamp = (chrono::Utc::now().timestamp_millis()).max(last + 1);
+        self.events.insert(0, json!({
+            "type": event_type,
+            "status": self.state,
+            "request": null,
+            "source": source,
+            "timestamp": timestamp,
+        }));
+    }
+}
+
+impl FakeFlaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap()
+    }
+
+    fn next_id(state: &mut FakeState) -> String {
+        state.counter += 1;
+        // Deterministic, but shaped like real machine IDs
+        format!("{:014x}", 0x3d8d_0000_0000u64 + state.counter * 0x1_0f2b)
+    }
+
+    /// Registers an app in the `personal` organization, as if it had been created with the Apps API.
+    pub fn create_app(&self, name: &str) {
+        let mut state = self.lock();
+        let id = Self::next_id(&mut state);
+        state.apps.entry(name.to_string()).or_insert_with(|| FakeApp {
+            id,
+            org_slug: "personal".to_string(),
+            network: "default".to_string(),
+            machines: BTreeMap::new(),
+        });
+    }
+
+    /// A client for `app_name` that sends its requests straight to this fake.
+    /// The app is created if it doesn't exist yet.
+    pub fn client(&self, app_name: &str) -> Client {
+        self.create_app(app_name);
+        let settings = FlapsSettings::builder()
+            .base_url(FAKE_BASE_URL)
+            .app_name(app_name)
+            .build();
+        Client::with_transport(settings, Arc::new(self.clone())).expect("valid fake client settings")
+    }
+
+    /// Queues the result of the next exec on a machine. Without one, execs succeed with no output.
+    pub fn push_exec_result(&self, app_name: &str, machine_id: &str, result: FakeExecResult) {
+        if let Some(m) = self.lock().apps.get_mut(app_name).and_then(|a| a.machines.get_mut(machine_id)) {
+            m.exec_results.push_back(result);
+        }
+    }
+
+    /// A snapshot of a machine, for assertions.
+    pub fn machine(&self, app_name: &str, machine_id: &str) -> Option<Machine> {
+        let state = self.lock();
+        let machine = state.apps.get(app_name)?.machines.get(machine_id)?;
+        serde_json::from_value(machine.to_json()).ok()
+    }
+
+    /// Whether the machine is currently cordoned.
+    pub fn is_cordoned(&self, app_name: &str, machine_id: &str) -> bool {
+        self.lock().apps.get(app_name).and_then(|a| a.machines.get(machine_id)).is_some_and(|m| m.cordoned)
+    }
+
+    /// Forces a machine into a state, e.g. to simulate a crash (`stopped`) or a host failure (`failed`).
+    pub fn set_machine_state(&self, app_name: &str, machine_id: &str, new_state: State) {
+        if let Some(m) = self.lock().apps.get_mut(app_name).and_then(|a| a.machines.get_mut(machine_id)) {
+            m.transition(new_state, "exit", "flyd");
+        }
+        self.changed.notify_waiters();
+    }
+
+    /// Handles a request. Shared by the [`Transport`] implementation and the HTTP server.
+    async fn handle(&self, method: &http::Method, url: &url::Url, body: &str, nonce: Option<&str>) -> (http::StatusCode, Value) {
+        let result = self.route(method, url, body, nonce).await;
+        if result.is_ok() && *method != http::Method::GET {
+            self.changed.notify_waiters();
+        }
+        match result {
+            Ok(value) => (http::StatusCode::OK, value),
+            Err(e) => {
+                let mut body = json!({ "error": e.error });
+                if let Some(message) = e.message {
+                    body["message"] = json!(message);
+                }
+                (http::StatusCode::from_u16(e.status).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR), body)
+            },
+        }
+    }
+
+    async fn route(&self, method: &http::Method, url: &url::Url, body: &str, nonce: Option<&str>) -> FakeResult {
+        let segments: Vec<String> = url.path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).map(|s| urlencoding::decode(s).map(|s| s.into_owned()).unwrap_or_else(|_| s.to_string())).collect())
+            .unwrap_or_default();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let req = FakeRequest {
+            method,
+            query: url.query_pairs().into_owned().collect(),
+            body: serde_json::from_str(body).unwrap_or(Value::Null),
+            nonce,
+        };
+        let (query, body) = (&req.query, &req.body);
+
+        use http::Method as M;
+        match (method, segments.as_slice()) {
+            (&M::POST, ["v1", "apps"]) => self.create_app_route(body),
+            (&M::GET, ["v1", "apps"]) => self.list_apps(query.get("org_slug")),
+            (&M::GET, ["v1", "apps", app]) => self.get_app(app),
+            (&M::DELETE, ["v1", "apps", app]) => self.delete_app(app),
+
+            (&M::GET, ["v1", "apps", app, "machines"]) => self.list_machines(app, query),
+            (&M::POST, ["v1", "apps", app, "machines"]) => self.launch(app, body),
+            (&M::GET, ["v1", "apps", app, "machines", id]) => self.with_machine(app, id, |m| Ok(m.to_json())),
+            (&M::POST, ["v1", "apps", app, "machines", id]) => self.update(app, id, body, nonce),
+            (&M::DELETE, ["v1", "apps", app, "machines", id]) => self.destroy(app, id, query.get("kill").is_some_and(|k| k == "true"), nonce),
+            (&M::GET, ["v1", "apps", app, "machines", id, "wait"]) => self.wait(app, id, query).await,
+            (_, ["v1", "apps", app, "machines", id, rest @ ..]) => self.machine_action(&req, app, id, rest),
+
+            _ => Err(FakeError::not_found("route")),
+        }
+    }
+
+    fn create_app_route(&self, body: &Value) -> FakeResult {
+        let name = body["app_name"].as_str().ok_or_else(|| FakeError::new(400, "app_name is required"))?;
+        if self.lock().apps.contains_key(name) {
+            return Err(FakeError::new(409, format!("app {name} already exists")));
+        }
+        self.create_app(name);
+        let mut state = self.lock();
+        let app = state.apps.get_mut(name).unwrap();
+        if let Some(org) = body["org_slug"].as_str() {
+            app.org_slug = org.to_string();
+        }
+        if let Some(network) = body["network"].as_str() {
+            app.network = network.to_string();
+        }
+        Ok(Value::Null)
+    }
+
+    fn list_apps(&self, org_slug: Option<&String>) -> FakeResult {
+        let state = self.lock();
+        let apps: Vec<_> = state.apps.iter()
+            .filter(|(_, a)| org_slug.map_or(true, |o| *o == a.org_slug))
+            .map(|(name, a)| json!({
+                "id": a.id,
+                "name": name,
+                "machine_count": a.machines.values().filter(|m| m.state != State::Destroyed).count(),
+                "network": a.network,
+            }))
+            .collect();
+        Ok(json!({ "total_apps": apps.len(), "apps": apps }))
+    }
+
+    fn get_app(&self, name: &str) -> FakeResult {
+        let state = self.lock();
+        let app = state.apps.get(name).ok_or_else(|| FakeError::not_found("app"))?;
+        Ok(json!({
+            "id": app.id,
+            "name": name,
+            "status": "deployed",
+            "organization": { "name": app.org_slug, "slug": app.org_slug },
+        }))
+    }
+
+    fn delete_app(&self, name: &str) -> FakeResult {
+        self.lock().apps.remove(name).ok_or_else(|| FakeError::not_found("app"))?;
+        Ok(Value::Null)
+    }
+
+    fn with_app<T>(&self, app: &str, f: impl FnOnce(&mut FakeApp) -> Result<T, FakeError>) -> Result<T, FakeError> {
+        let mut state = self.lock();
+        let app = state.apps.get_mut(app).ok_or_else(|| FakeError::not_found("app"))?;
+        f(app)
+    }
+
+    fn with_machine<T>(&self, app: &str, id: &str, f: impl FnOnce(&mut FakeMachine) -> Result<T, FakeError>) -> Result<T, FakeError> {
+        self.with_app(app, |app| {
+            let machine = app.machines.get_mut(id).ok_or_else(|| FakeError::not_found("machine"))?;
+            f(machine)
+        })
+    }
+
+    fn list_machines(&self, app: &str, query: &BTreeMap<String, String>) -> FakeResult {
+        let include_deleted = query.get("include_deleted").is_some_and(|v| v == "true");
+        let states: Option<Vec<&str>> = query.get("state").map(|s| s.split(',').collect());
+        let metadata: Vec<(&str, &String)> = query.iter()
+            .filter_map(|(k, v)| k.strip_prefix("metadata.").map(|k| (k, v)))
+            .collect();
+
+        self.with_app(app, |app| {
+            Ok(Value::Array(app.machines.values()
+                .filter(|m| include_deleted || m.state != State::Destroyed)
+                .filter(|m| query.get("region").map_or(true, |r| *r == m.region))
+                .filter(|m| states.as_ref().map_or(true, |s| s.contains(&m.state.name())))
+                .filter(|m| metadata.iter().all(|(k, v)| m.config["metadata"][k].as_str() == Some(v.as_str())))
+                .map(FakeMachine::to_json)
+                .collect()))
+        })
+    }
+
+    fn launch(&self, app_name: &str, body: &Value) -> FakeResult {
+        let config = body.get("config").cloned().filter(|c| !c.is_null()).ok_or_else(|| FakeError::new(400, "config is required"))?;
+        if config["image"].as_str().unwrap_or_default().is_empty() {
+            return Err(FakeError::new(422, "invalid config: image is required"));
+        }
+
+        let mut state = self.lock();
+        let id = Self::next_id(&mut state);
+        let instance_id = Self::next_id(&mut state);
+        let nonce = Self::next_id(&mut state);
+        let app = state.apps.get_mut(app_name).ok_or_else(|| FakeError::not_found("app"))?;
+
+        let now = rfc3339_now();
+        let mut machine = FakeMachine {
+            name: body["name"].as_str().map(str::to_string).unwrap_or_else(|| format!("machine-{}", &id[id.len() - 6..])),
+            id: id.clone(),
+            state: State::Created,
+            region: body["region"].as_str().unwrap_or("ord").to_string(),
+            instance_id,
+            config,
+            created_at: now.clone(),
+            updated_at: now,
+            events: Vec::new(),
+            lease: None,
+            cordoned: false,
+            exec_results: VecDeque::new(),
+        };
+        machine.transition(State::Created, "launch", "user");
+        if let Some(ttl) = body["lease_ttl"].as_i64() {
+            machine.lease = Some(FakeLease { nonce, expires_at: unix_now() + ttl, owner: "fake@flaps.test".to_string() });
+        }
+
+        // The response shows the machine as created; it has started by the time anyone looks again.
+        let response = machine.to_json();
+        if body["skip_launch"].as_bool() == Some(true) {
+            machine.transition(State::Stopped, "exit", "flyd");
+        } else {
+            machine.transition(State::Started, "start", "flyd");
+        }
+        app.machines.insert(id, machine);
+        Ok(response)
+    }
+
+    fn update(&self, app: &str, id: &str, body: &Value, nonce: Option<&str>) -> FakeResult {
+        let instance_id = Self::next_id(&mut self.lock());
+        self.with_machine(app, id, |m| {
+            m.check_lease(nonce)?;
+            if m.state == State::Destroyed {
+                return Err(FakeError::new(412, "machine is destroyed"));
+            }
+            if let Some(config) = body.get("config").filter(|c| !c.is_null()) {
+                m.config = config.clone();
+            }
+            m.instance_id = instance_id;
+            m.transition(State::Replacing, "update", "user");
+            if body["skip_launch"].as_bool() == Some(true) {
+                m.transition(State::Stopped, "exit", "flyd");
+            } else {
+                m.transition(State::Started, "start", "flyd");
+            }
+            Ok(m.to_json())
+        })
+    }
+
+    fn destroy(&self, app: &str, id: &str, kill: bool, nonce: Option<&str>) -> FakeResult {
+        self.with_machine(app, id, |m| {
+            m.check_lease(nonce)?;
+            match m.state {
+                State::Destroyed => Err(FakeError::not_found("machine")),
+                State::Started if !kill => Err(FakeError::new(412, "unable to destroy machine, not currently stopped or suspended")),
+                _ => {
+                    m.lease = None;
+                    m.transition(State::Destroyed, "destroy", "user");
+                    Ok(json!({ "ok": true }))
+                },
+            }
+        })
+    }
+
+    async fn wait(&self, app: &str, id: &str, query: &BTreeMap<String, String>) -> FakeResult {
+        let target = query.get("state").map(String::as_str).unwrap_or("started");
+        if !matches!(target, "started" | "stopped" | "suspended" | "destroyed") {
+            return Err(FakeError::new(400, format!("invalid state: {target}")));
+        }
+        let timeout = query.get("timeout").and_then(|t| t.parse().ok()).unwrap_or(60);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout);
+
+        loop {
+            // Register for changes before looking, so that none are missed in between.
+            let changed = self.changed.notified();
+            if self.with_machine(app, id, |m| Ok(m.state.name() == target))? {
+                return Ok(json!({ "ok": true }));
+            }
+            if tokio::time::timeout_at(deadline, changed).await.is_err() {
+                return Err(FakeError::new(408, format!("deadline_exceeded: machine did not reach state {target}")));
+            }
+        }
+    }
+
+    fn machine_action(&self, req: &FakeRequest, app: &str, id: &str, action: &[&str]) -> FakeResult {
+        let FakeRequest { method, query, body, nonce } = req;
+        let nonce = *nonce;
+        use http::Method as M;
+        let owner = "fake@flaps.test".to_string();
+        let new_nonce = Self::next_id(&mut self.lock());
+
+        self.with_machine(app, id, |m| {
+            if m.state == State::Destroyed {
+                return Err(FakeError::not_found("machine"));
+            }
+            match (*method, action) {
+                (&M::POST, ["start"]) => {
+                    m.check_lease(nonce)?;
+                    let previous_state = m.state.clone();
+                    if !matches!(previous_state, State::Started) {
+                        m.transition(State::Started, "start", "user");
+                    }
+                    Ok(json!({ "message": "", "status": "success", "previous_state": previous_state }))
+                },
+                (&M::POST, ["stop"]) | (&M::POST, ["signal"]) => {
+                    m.check_lease(nonce)?;
+                    m.transition(State::Stopped, "exit", "user");
+                    Ok(json!({ "ok": true }))
+                },
+                (&M::POST, ["suspend"]) => {
+                    m.check_lease(nonce)?;
+                    if m.state != State::Started {
+                        return Err(FakeError::new(412, "machine is not started"));
+                    }
+                    m.transition(State::Suspended, "suspend", "user");
+                    Ok(json!({ "ok": true }))
+                },
+                (&M::POST, ["restart"]) => {
+                    m.check_lease(nonce)?;
+                    m.transition(State::Started, "restart", "user");
+                    Ok(json!({ "ok": true }))
+                },
+                (&M::POST, ["cordon"]) | (&M::POST, ["uncordon"]) => {
+                    m.check_lease(nonce)?;
+                    m.cordoned = action == ["cordon"];
+                    Ok(json!({ "ok": true }))
+                },
+
+                (&M::GET, ["lease"]) => match m.active_lease() {
+                    Some(lease) => Ok(lease_json(lease)),
+                    None => Err(FakeError { status: 404, error: "not_found".to_string(), message: Some("lease not found".to_string()) }),
+                },
+                (&M::POST, ["lease"]) => {
+                    if m.active_lease().is_some() {
+                        return Err(FakeError::new(409, "lease currently held"));
+                    }
+                    let ttl = query.get("ttl").and_then(|t| t.parse().ok()).unwrap_or(DEFAULT_LEASE_TTL);
+                    let lease = FakeLease { nonce: new_nonce, expires_at: unix_now() + ttl, owner };
+                    let res = lease_json(&lease);
+                    m.lease = Some(lease);
+                    Ok(res)
+                },
+                (&M::POST, ["lease", "refresh"]) => {
+                    let lease = m.lease.as_mut()
+                        .filter(|l| Some(l.nonce.as_str()) == nonce)
+                        .ok_or_else(|| FakeError { status: 412, error: "lease mismatch".to_string(), message: Some("lease mismatch".to_string()) })?;
+                    let ttl = query.get("ttl").and_then(|t| t.parse().ok()).unwrap_or(DEFAULT_LEASE_TTL);
+                    lease.expires_at = unix_now() + ttl;
+                    Ok(lease_json(lease))
+                },
+                (&M::DELETE, ["lease"]) => {
+                    m.check_lease(nonce)?;
+                    m.lease = None;
+                    Ok(json!({ "ok": true }))
+                },
+
+                (&M::POST, ["exec"]) => {
+                    if m.state != State::Started {
+                        return Err(FakeError::new(412, "machine is not started"));
+                    }
+                    if body["command"].as_array().map_or(true, Vec::is_empty) && body["cmd"].as_str().map_or(true, str::is_empty) {
+                        return Err(FakeError::new(400, "command is required"));
+                    }
+                    let res = m.exec_results.pop_front().unwrap_or_default();
+                    Ok(json!({ "exit_code": res.exit_code, "stdout": res.stdout, "stderr": res.stderr }))
+                },
+                (&M::GET, ["ps"]) => Ok(json!([])),
+
+                (&M::GET, ["metadata"]) => Ok(m.config.get("metadata").cloned().filter(|v| !v.is_null()).unwrap_or_else(|| json!({}))),
+                (&M::POST, ["metadata", key]) => {
+                    m.check_lease(nonce)?;
+                    let value = body["value"].as_str().ok_or_else(|| FakeError::new(400, "value is required"))?;
+                    if !m.config["metadata"].is_object() {
+                        m.config["metadata"] = json!({});
+                    }
+                    m.config["metadata"][*key] = json!(value);
+                    Ok(Value::Null)
+                },
+                (&M::DELETE, ["metadata", key]) => {
+                    m.check_lease(nonce)?;
+                    if let Some(metadata) = m.config["metadata"].as_object_mut() {
+                        metadata.remove(*key);
+                    }
+                    Ok(Value::Null)
+                },
+
+                _ => Err(FakeError::not_found("route")),
+            }
+        })
+    }
+}
+
+fn lease_json(lease: &FakeLease) -> Value {
+    json!({
+        "status": "success",
+        "data": { "nonce": lease.nonce, "expires_at": lease.expires_at, "owner": lease.owner },
+        "message": "",
+        "code": "",
+    })
+}
+
+#[async_trait::async_trait]
+impl Transport for FakeFlaps {
+    async fn make_request(&self, _user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> crate::api::flaps::Result<TransportResult> {
+        let nonce = headers.iter().find(|h| h.name() == "fly-machine-lease-nonce").map(|h| h.value().to_string());
+        let (status, body) = self.handle(&method, &url, &json, nonce.as_deref()).await;
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert("content-type", http::HeaderValue::from_static("application/json"));
+        headers.insert("fly-request-id", http::HeaderValue::from_str(&format!("fake-{}", Self::next_id(&mut self.lock()))).unwrap());
+        Ok(TransportResult::new(status, headers, serde_json::to_vec(&body)?.into()))
+    }
+}
+
+#[cfg(test)]
+fn test_launch_input() -> crate::api::flaps::LaunchMachineInput {
+    crate::api::flaps::LaunchMachineInput {
+        config: Some(crate::entities::machine::Config {
+            image: "registry.fly.io/my-app:deployment-1".to_string(),
+            ..Default::default()
+        }),
+        region: Some("ord".to_string()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_machine_lifecycle() {
+    use crate::api::flaps::{FlapsError, StopMachineInput, RemoveMachineInput};
+
+    let fake = FakeFlaps::new();
+    let client = fake.client("my-app");
+
+    let machine = client.launch(test_launch_input()).await.unwrap();
+    assert_eq!(machine.state, State::Created);
+    assert_eq!(machine.image_ref.repository, "my-app");
+    client.wait(&machine, Some(State::Started), Duration::from_secs(1)).await.unwrap();
+
+    client.stop(StopMachineInput { id: machine.id.clone(), signal: "SIGINT".to_string(), timeout: Duration::from_secs(5).into() }, None).await.unwrap();
+    client.wait(&machine, Some(State::Stopped), Duration::from_secs(1)).await.unwrap();
+    assert!(matches!(
+        client.wait(&machine, Some(State::Started), Duration::from_secs(1)).await,
+        Err(FlapsError::DesiredStateNotReached { .. }),
+    ));
+
+    client.destroy(RemoveMachineInput { id: machine.id.clone(), kill: false }, None).await.unwrap();
+    assert!(client.list(None).await.unwrap().is_empty());
+    assert!(matches!(client.get(&machine.id).await.unwrap().state, State::Destroyed));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_leases() {
+    let fake = FakeFlaps::new();
+    let client = fake.client("my-app");
+    let machine = client.launch(test_launch_input()).await.unwrap();
+
+    let lease = client.acquire_lease(&machine.id, Some(60)).await.unwrap();
+    assert!(client.acquire_lease(&machine.id, None).await.is_err());
+    assert!(client.cordon(&machine.id, None).await.is_err());
+
+    client.cordon(&machine.id, Some(lease.data.nonce.clone())).await.unwrap();
+    assert!(fake.is_cordoned("my-app", &machine.id));
+
+    client.release_lease(&machine.id, Some(lease.data.nonce)).await.unwrap();
+    assert!(client.find_lease(&machine.id).await.unwrap().is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_validate_configs() {
+    use crate::api::flaps::FlapsError;
+
+    let fake = FakeFlaps::new();
+    fake.create_app("my-app");
+    let settings = FlapsSettings::builder().base_url(FAKE_BASE_URL).app_name("my-app").validate_configs(true).build();
+    let client = Client::with_transport(settings, Arc::new(fake)).unwrap();
+
+    let mut input = test_launch_input();
+    input.config.as_mut().unwrap().schedule = Some("yearly".to_string());
+    match client.launch(input).await {
+        Err(FlapsError::InvalidConfig(errors)) => assert_eq!(errors[0].path, "schedule"),
+        other => panic!("expected an invalid config, got {other:?}"),
+    }
+    client.launch(test_launch_input()).await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_deploy_secrets() {
+    use crate::api::flaps::StopMachineInput;
+
+    let fake = FakeFlaps::new();
+    let client = fake.client("my-app");
+    let started = client.launch(test_launch_input()).await.unwrap();
+    let stopped = client.launch(test_launch_input()).await.unwrap();
+    let leased = client.launch(test_launch_input()).await.unwrap();
+    client.stop(StopMachineInput { id: stopped.id.clone(), signal: "SIGINT".to_string(), timeout: Duration::from_secs(5).into() }, None).await.unwrap();
+    let _lease = client.acquire_lease(&leased.id, Some(60)).await.unwrap();
+
+    let deployments = client.deploy_secrets(Some(2)).await.unwrap();
+    assert_eq!(deployments.len(), 3);
+    let result = |id: &str| &deployments.iter().find(|d| d.machine_id == id).unwrap().result;
+
+    assert!(result(&started.id).is_ok());
+    assert!(result(&stopped.id).is_ok());
+    // Someone else's lease only fails that machine
+    assert!(result(&leased.id).is_err());
+
+    assert_eq!(fake.machine("my-app", &started.id).unwrap().state, State::Started);
+    assert_eq!(fake.machine("my-app", &stopped.id).unwrap().state, State::Stopped);
+    assert!(client.find_lease(&started.id).await.unwrap().is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_metrics_observer() {
+    use crate::api::flaps::{MetricsObserver, RemoveMachineInput, RequestInfo, RequestOutcome};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+    impl MetricsObserver for Recorder {
+        fn request_finished(&self, request: &RequestInfo, outcome: &RequestOutcome) {
+            if let Some(kind) = outcome.error {
+                self.0.lock().unwrap().push(format!("error {} {kind}", request.endpoint));
+            }
+        }
+        fn lease_acquired(&self) {
+            self.0.lock().unwrap().push("acquired".to_string());
+        }
+        fn lease_released(&self) {
+            self.0.lock().unwrap().push("released".to_string());
+        }
+        fn lease_guard_started(&self) {
+            self.0.lock().unwrap().push("guard started".to_string());
+        }
+        fn lease_guard_finished(&self) {
+            self.0.lock().unwrap().push("guard finished".to_string());
+        }
+    }
+
+    let fake = FakeFlaps::new();
+    fake.create_app("my-app");
+    let recorder = Arc::new(Recorder::default());
+    let settings = FlapsSettings::builder().base_url(FAKE_BASE_URL).app_name("my-app").metrics_observer(recorder.clone()).build();
+    let client = Client::with_transport(settings, Arc::new(fake)).unwrap();
+
+    let machine = client.launch(test_launch_input()).await.unwrap();
+    // Timing out is how waits report that the state wasn't reached yet, not an error
+    assert!(client.wait(&machine, Some(State::Stopped), Duration::from_secs(1)).await.is_err());
+
+    let lease = client.acquire_lease(&machine.id, Some(60)).await.unwrap();
+    client.destroy(RemoveMachineInput { id: machine.id.clone(), kill: true }, Some(lease.data.nonce)).await.unwrap();
+
+    assert_eq!(*recorder.0.lock().unwrap(), ["acquired", "released"]);
+    recorder.0.lock().unwrap().clear();
+
+    // Guards are counted once each, however they end
+    let machine = client.launch(test_launch_input()).await.unwrap();
+    client.lease(&machine.id, Some(60)).await.unwrap().release().await.unwrap();
+    client.lease(&machine.id, Some(60)).await.unwrap().destroy(true).await.unwrap();
+    assert_eq!(*recorder.0.lock().unwrap(), [
+        "acquired", "guard started", "released", "guard finished",
+        "acquired", "guard started", "released", "guard finished",
+    ]);
+    recorder.0.lock().unwrap().clear();
+
+    let machine = client.launch(test_launch_input()).await.unwrap();
+    drop(client.lease(&machine.id, Some(60)).await.unwrap());
+    assert_eq!(*recorder.0.lock().unwrap(), ["acquired", "guard started", "guard finished"]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_watch() {
+    use futures::StreamExt;
+    use crate::api::flaps::{MachineWatchEvent, RetryPolicy};
+
+    /// Fails the first `failures` requests with `status`, then passes requests on to the fake.
+    struct Flaky {
+        fake: FakeFlaps,
+        status: u16,
+        failures: Mutex<u32>,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for Flaky {
+        async fn make_request(&self, user_agent: &str, method: http::Method, url: url::Url, json: String, headers: Vec<HeaderPair>) -> crate::api::flaps::Result<TransportResult> {
+            {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    let body = bytes::Bytes::from_static(br#"{"error":"unavailable"}"#);
+                    return Ok(TransportResult::new(http::StatusCode::from_u16(self.status).unwrap(), http::HeaderMap::new(), body));
+                }
+            }
+            self.fake.make_request(user_agent, method, url, json, headers).await
+        }
+    }
+
+    let fake = FakeFlaps::new();
+    let client = fake.client("my-app");
+    let machine = client.launch(test_launch_input()).await.unwrap();
+    let machine = client.get(&machine.id).await.unwrap();
+    let flaky = |status, failures| {
+        let settings = FlapsSettings::builder()
+            .base_url(FAKE_BASE_URL)
+            .app_name("my-app")
+            .retry_policy(RetryPolicy { max_attempts: 1, ..Default::default() })
+            .build();
+        Client::with_transport(settings, Arc::new(Flaky { fake: fake.clone(), status, failures: Mutex::new(failures) })).unwrap()
+    };
+
+    // Retryable errors are polled through
+    fake.set_machine_state("my-app", &machine.id, State::Stopped);
+    let events: Vec<_> = flaky(503, 2).watch(&machine).take(2).collect().await;
+    assert!(matches!(events[0], Ok(MachineWatchEvent::Event(ref e)) if e.status == "stopped"));
+    assert!(matches!(events[1], Ok(MachineWatchEvent::StateChanged { from: State::Started, to: State::Stopped })));
+
+    // Others end the stream
+    let events: Vec<_> = flaky(500, 1).watch(&machine).collect().await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].as_ref().unwrap_err().status(), Some(http::StatusCode::INTERNAL_SERVER_ERROR));
+
+    // A machine that can't be found has been destroyed
+    let mut missing = machine.clone();
+    missing.id = "3d8d0000000000".to_string();
+    let events: Vec<_> = client.watch(&missing).collect().await;
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], Ok(MachineWatchEvent::StateChanged { from: State::Started, to: State::Destroyed })));
+}
@@ -0,0 +1,93 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{service::{make_service_fn, service_fn}, Body, Request, Response};
+
+use super::FakeFlaps;
+
+/// A [`FakeFlaps`] served over HTTP on a local port, created with [`FakeFlaps::bind`].
+/// The server shuts down when this is dropped.
+pub struct FakeFlapsServer {
+    addr: SocketAddr,
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl FakeFlapsServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL to use as the client's base URL, e.g. `http://127.0.0.1:49152`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for FakeFlapsServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn serve(fake: FakeFlaps, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let nonce = parts.headers.get("fly-machine-lease-nonce").and_then(|v| v.to_str().ok()).map(str::to_string);
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+
+    // Only the path and query matter for routing
+    let url = url::Url::parse(super::FAKE_BASE_URL).unwrap().join(&parts.uri.to_string()).unwrap();
+    let (status, json) = fake.handle(&parts.method, &url, &String::from_utf8_lossy(&body), nonce.as_deref()).await;
+    let request_id = format!("fake-{}", FakeFlaps::next_id(&mut fake.lock()));
+
+    Ok(Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .header("fly-request-id", request_id)
+        .body(Body::from(json.to_string()))
+        .unwrap())
+}
+
+impl FakeFlaps {
+    /// Serves this fake over HTTP on `127.0.0.1`, on a free port. Must be called within a tokio runtime.
+    pub async fn bind(&self) -> std::io::Result<FakeFlapsServer> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let fake = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let fake = fake.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| serve(fake.clone(), req))) }
+        });
+
+        let (shutdown, rx) = tokio::sync::oneshot::channel();
+        let server = hyper::Server::from_tcp(listener)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+            .serve(make_service)
+            .with_graceful_shutdown(async { let _ = rx.await; });
+        tokio::spawn(server);
+
+        Ok(FakeFlapsServer { addr, shutdown: Some(shutdown) })
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_bind() {
+    use crate::api::flaps::{Client, FlapsSettings};
+
+    let fake = FakeFlaps::new();
+    fake.create_app("my-app");
+    let server = fake.bind().await.unwrap();
+
+    let client = Client::new(FlapsSettings::builder()
+        .base_url(server.base_url())
+        .auth_token("fake")
+        .app_name("my-app")
+        .build()).unwrap();
+
+    let machine = client.launch(super::test_launch_input()).await.unwrap();
+    assert_eq!(client.list(None).await.unwrap()[0].id, machine.id);
+    assert!(matches!(client.get(&"nope").await, Err(crate::api::flaps::FlapsError::NotFound(_))));
+}